@@ -1,43 +1,74 @@
-use isahc::prelude::*;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::iter;
 
-use crate::backend::{Addon, Flavor, Source, Version};
+use crate::backend::{
+    request::Client, Addon, Checksums, Dependency, DependencyKind, Fetched, Flavor, GameVersion,
+    Partition, ReleaseChannel, Source, Version,
+};
+use crate::config::{Config, CurseConfig};
 use crate::error::Error;
+use crate::utility::{iso8601_to_datetime, null_to_default, Lenient};
 
-impl From<File> for Version {
-    fn from(file: File) -> Self {
-        Version {
-            game_version: file.game_version.first().cloned(),
-            flavor: file.game_version_flavor,
-            date: file.file_date,
-        }
-    }
-}
+mod legacy;
 
 impl From<Package> for Addon {
     fn from(package: Package) -> Self {
-        let files = package
-            .latest_files
-            .into_iter()
-            .filter(|f| f.release_type == 1 || f.release_type == 2)
-            .collect::<Vec<File>>();
-        let files_cloned = files.clone();
-        let versions = files
+        // A file can support several flavors. We want the newest file for
+        // each flavor and release channel.
+        let mut newest: Vec<(Flavor, ReleaseChannel, &File)> = vec![];
+        for file in package.latest_files.iter() {
+            let channel = match channel_for_release_type(file.release_type) {
+                Some(channel) => channel,
+                None => continue,
+            };
+            for flavor in file.flavors() {
+                match newest
+                    .iter_mut()
+                    .find(|(f, c, _)| *f == flavor && *c == channel)
+                {
+                    Some(entry) if entry.2.id < file.id => entry.2 = file,
+                    Some(_) => {}
+                    None => newest.push((flavor, channel, file)),
+                }
+            }
+        }
+
+        let versions = newest
             .into_iter()
-            .filter(|f| {
-                // We only want the newest for each flavor.
-                !files_cloned
+            .map(|(flavor, channel, file)| Version {
+                release_channel: channel,
+                download_url: file.download_url.clone(),
+                file_name: file.file_name.clone(),
+                file_size: file.file_length,
+                folders: file.modules.iter().map(|m| m.name.clone()).collect(),
+                dependencies: file
+                    .dependencies
                     .iter()
-                    .any(|b| b.game_version_flavor == f.game_version_flavor && b.id > f.id)
+                    .filter_map(|d| d.dependency())
+                    .collect(),
+                checksums: file.checksums(),
+                ..Version::new(
+                    flavor,
+                    file.game_versions_for_flavor(flavor),
+                    file.file_date,
+                )
             })
-            .map(Version::from)
             .collect();
+
         Addon {
             id: package.id,
+            source_id: None,
             name: package.name,
-            url: package.website_url,
+            url: package.links.website_url,
             number_of_downloads: package.download_count.round() as u64,
             summary: package.summary,
+            authors: package.authors.into_iter().map(|a| a.name).collect(),
+            version: None,
+            logo_url: package.logo.map(|l| l.url),
+            screenshot_urls: package.screenshots.into_iter().map(|s| s.url).collect(),
             versions,
             categories: package.categories.into_iter().map(|c| c.name).collect(),
             source: Source::Curse,
@@ -45,19 +76,178 @@ impl From<Package> for Addon {
     }
 }
 
+#[derive(Deserialize, Clone, Debug)]
+struct Container {
+    data: Lenient<Package>,
+    pagination: Pagination,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Pagination {
+    index: usize,
+    page_size: usize,
+    result_count: usize,
+    total_count: usize,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 struct Category {
+    id: i32,
     name: String,
 }
 
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct FileHash {
+    value: String,
+    /// `1` is SHA-1 and `2` is MD5.
+    algo: i32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FileDependency {
+    mod_id: i32,
+    relation_type: i32,
+}
+
+impl FileDependency {
+    /// Returns `None` for relations which are not dependencies, eg.
+    /// incompatible addons.
+    fn dependency(&self) -> Option<Dependency> {
+        kind_for_relation_type(self.relation_type).map(|kind| Dependency {
+            id: self.mod_id,
+            kind,
+        })
+    }
+}
+
+/// An addon folder in a file.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Module {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Author {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Image {
+    url: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Links {
+    #[serde(deserialize_with = "null_to_default::deserialize")]
+    website_url: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SortableGameVersion {
+    game_version: String,
+    game_version_type_id: Option<i32>,
+}
+
+/// Returns `ReleaseChannel` for a CurseForge release type.
+fn channel_for_release_type(release_type: i32) -> Option<ReleaseChannel> {
+    match release_type {
+        1 => Some(ReleaseChannel::Stable),
+        2 => Some(ReleaseChannel::Beta),
+        3 => Some(ReleaseChannel::Alpha),
+        _ => None,
+    }
+}
+
+/// Returns `DependencyKind` for a CurseForge relation type.
+fn kind_for_relation_type(relation_type: i32) -> Option<DependencyKind> {
+    match relation_type {
+        1 => Some(DependencyKind::Embedded),
+        2 => Some(DependencyKind::Optional),
+        3 => Some(DependencyKind::Required),
+        4 => Some(DependencyKind::Tool),
+        _ => None,
+    }
+}
+
+impl SortableGameVersion {
+    /// Returns `Flavor` by the type id, or by the version for unknown type ids.
+    fn flavor(&self) -> Option<Flavor> {
+        self.game_version_type_id
+            .and_then(flavor_for_type_id)
+            .or_else(|| self.version().and_then(|v| v.flavor()))
+    }
+
+    fn version(&self) -> Option<GameVersion> {
+        self.game_version.parse().ok()
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 struct File {
     id: i32,
-    file_date: String,
-    game_version_flavor: Flavor,
-    game_version: Vec<String>,
+    #[serde(deserialize_with = "iso8601_to_datetime::deserialize")]
+    file_date: Option<DateTime<Utc>>,
     release_type: i32,
+    /// Missing if the author does not allow third party distribution.
+    #[serde(default)]
+    download_url: Option<String>,
+    #[serde(default)]
+    file_name: Option<String>,
+    #[serde(default)]
+    file_length: Option<u64>,
+    #[serde(default, deserialize_with = "null_to_default::deserialize")]
+    modules: Vec<Module>,
+    #[serde(default, deserialize_with = "null_to_default::deserialize")]
+    dependencies: Vec<FileDependency>,
+    #[serde(default, deserialize_with = "null_to_default::deserialize")]
+    hashes: Vec<FileHash>,
+    #[serde(default)]
+    file_fingerprint: Option<u32>,
+    #[serde(deserialize_with = "null_to_default::deserialize")]
+    sortable_game_versions: Vec<SortableGameVersion>,
+}
+
+impl File {
+    /// Returns every `Flavor` the file supports.
+    fn flavors(&self) -> Vec<Flavor> {
+        let mut flavors = vec![];
+        for version in self.sortable_game_versions.iter() {
+            if let Some(flavor) = version.flavor() {
+                if !flavors.contains(&flavor) {
+                    flavors.push(flavor);
+                }
+            }
+        }
+        flavors
+    }
+
+    fn checksums(&self) -> Checksums {
+        let hash = |algo| {
+            self.hashes
+                .iter()
+                .find(|h| h.algo == algo)
+                .map(|h| h.value.to_lowercase())
+        };
+        Checksums {
+            md5: hash(2),
+            sha1: hash(1),
+            fingerprint: self.file_fingerprint,
+        }
+    }
+
+    /// Returns every game version the file supports for `Flavor`.
+    fn game_versions_for_flavor(&self, flavor: Flavor) -> Vec<GameVersion> {
+        self.sortable_game_versions
+            .iter()
+            .filter(|v| v.flavor() == Some(flavor))
+            .filter_map(|v| v.version())
+            .collect()
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -65,41 +255,415 @@ struct File {
 struct Package {
     id: i32,
     name: String,
-    // TODO (casperstorm): Strangely enough this expect to be `f64`.
-    // If set to u64, we get the following error:
-    // "invalid type: floating point `338355764`, expected u64"
     download_count: f64,
+    #[serde(deserialize_with = "null_to_default::deserialize")]
     summary: String,
-    website_url: String,
+    links: Links,
+    #[serde(default, deserialize_with = "null_to_default::deserialize")]
+    authors: Vec<Author>,
+    #[serde(default)]
+    logo: Option<Image>,
+    #[serde(default, deserialize_with = "null_to_default::deserialize")]
+    screenshots: Vec<Image>,
+    #[serde(deserialize_with = "null_to_default::deserialize")]
     categories: Vec<Category>,
+    #[serde(deserialize_with = "null_to_default::deserialize")]
     latest_files: Vec<File>,
 }
 
-fn base_endpoint(page_size: usize, index: usize) -> String {
+/// Returns `Flavor` for a CurseForge game version type id.
+fn flavor_for_type_id(id: i32) -> Option<Flavor> {
+    match id {
+        517 => Some(Flavor::RETAIL),
+        67408 => Some(Flavor::CLASSIC_ERA),
+        73246 => Some(Flavor::CLASSIC_TBC),
+        _ => None,
+    }
+}
+
+/// Search does not return results past this index, so a single crawl sees
+/// at most this many projects.
+const MAX_INDEX: usize = 10_000;
+
+const PAGE_SIZE: usize = 50;
+
+/// A slice of the search which is crawled on its own.
+///
+/// Each slice is crawled in ascending and, if needed, descending name order
+/// to reach past `MAX_INDEX`.
+struct Slice {
+    name: String,
+    category_id: Option<i32>,
+}
+
+/// Result of crawling a single `Slice`.
+#[derive(Default)]
+struct Crawl {
+    packages: Vec<Package>,
+    ids: HashSet<i32>,
+    skipped: usize,
+    /// Number of projects the search reports for the slice.
+    total_count: usize,
+}
+
+impl Crawl {
+    /// Adds packages which are not already part of the crawl.
+    ///
+    /// Results shift while crawling, so the same project can show up on
+    /// two pages.
+    fn extend(&mut self, packages: Vec<Package>) {
+        for package in packages {
+            if self.ids.insert(package.id) {
+                self.packages.push(package);
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct CategoryContainer {
+    data: Vec<Category>,
+}
+
+fn base_endpoint(
+    config: &CurseConfig,
+    slice: &Slice,
+    sort_order: &str,
+    page_size: usize,
+    index: usize,
+) -> String {
+    let mut endpoint = format!(
+        "{}/v1/mods/search?gameId=1&sortField=4&sortOrder={}&pageSize={}&index={}",
+        config.api_url.trim_end_matches('/'),
+        sort_order,
+        page_size,
+        index
+    );
+    if let Some(category_id) = slice.category_id {
+        endpoint.push_str(&format!("&categoryId={}", category_id));
+    }
+    endpoint
+}
+
+fn endpoint_for_categories(config: &CurseConfig) -> String {
     format!(
-        "https://addons-ecs.forgesvc.net/api/v2/addon/search?gameId=1&pageSize={}&index={}",
-        page_size, index
+        "{}/v1/categories?gameId=1",
+        config.api_url.trim_end_matches('/')
     )
 }
 
-pub async fn get_addons() -> Result<Vec<Addon>, Error> {
-    let mut index: usize = 0;
-    let page_size: usize = 50;
-    let mut number_of_addons = page_size;
-    let mut addons: Vec<Addon> = vec![];
-    while page_size == number_of_addons {
-        let endpoint = base_endpoint(page_size, index);
-        let mut response = isahc::get_async(endpoint).await?;
-        let packages = response.json::<Vec<Package>>().await?;
-        let partials_addons = packages
-            .into_iter()
-            .map(Addon::from)
-            .collect::<Vec<Addon>>();
+/// Crawls `slice` in `sort_order` until the last page or `MAX_INDEX`.
+///
+/// The first page tells how many pages there are. The rest are fetched
+/// concurrently and reassembled in index order.
+async fn crawl_in_order(
+    client: &Client,
+    config: &CurseConfig,
+    slice: &Slice,
+    sort_order: &str,
+) -> Result<Crawl, Error> {
+    let get_page = |index: usize| {
+        let endpoint = base_endpoint(config, slice, sort_order, PAGE_SIZE, index);
+        async move { client.get_json::<Container>(&endpoint).await }
+    };
+
+    let first = get_page(0).await?;
+    let total_count = first.pagination.total_count;
+    let indices = (PAGE_SIZE..total_count.min(MAX_INDEX))
+        .step_by(PAGE_SIZE)
+        .take_while(|index| index + PAGE_SIZE <= MAX_INDEX);
+    let rest = stream::iter(indices)
+        .map(get_page)
+        .buffered(config.concurrency.max(1))
+        .try_collect::<Vec<Container>>()
+        .await?;
+
+    let mut crawl = Crawl {
+        total_count,
+        ..Crawl::default()
+    };
+    for container in iter::once(first).chain(rest) {
+        container.data.log_skipped(Source::Curse);
+        crawl.skipped += container.data.skipped.len();
+        crawl.extend(container.data.items);
+    }
+
+    Ok(crawl)
+}
+
+/// Crawls `slice` from both ends if it is larger than `MAX_INDEX`.
+async fn crawl(client: &Client, config: &CurseConfig, slice: &Slice) -> Result<Crawl, Error> {
+    let mut crawl = crawl_in_order(client, config, slice, "asc").await?;
+    if crawl.total_count > MAX_INDEX {
+        let tail = crawl_in_order(client, config, slice, "desc").await?;
+        crawl.skipped += tail.skipped;
+        crawl.extend(tail.packages);
+
+        if crawl.total_count > 2 * MAX_INDEX {
+            log::warn!(
+                "{}: partition {} has {} projects, only {} can be crawled",
+                Source::Curse,
+                slice.name,
+                crawl.total_count,
+                2 * MAX_INDEX
+            );
+        }
+    }
 
-        addons.extend_from_slice(&partials_addons);
-        number_of_addons = partials_addons.len();
-        index += page_size;
+    Ok(crawl)
+}
+
+pub async fn get_addons(config: &Config) -> Result<Fetched, Error> {
+    let curse = &config.curse;
+    if let Some(api_url) = &curse.legacy_api_url {
+        let client = Client::new(Source::Curse, &curse.request);
+        return legacy::get_addons(&client, api_url, curse.concurrency).await;
+    }
+
+    let api_key = curse.api_key().ok_or(Error::MissingApiKey {
+        backend: Source::Curse,
+    })?;
+    let client = Client::new(Source::Curse, &curse.request).header("x-api-key", &api_key);
+
+    // The whole search is crawled first. If it falls short of the total, for
+    // projects which lie past `MAX_INDEX`, each category is crawled as well.
+    let all = Slice {
+        name: "all".to_owned(),
+        category_id: None,
+    };
+    let first = crawl(&client, curse, &all).await?;
+    let total_count = first.total_count;
+
+    // Skipped records were found, they just could not be decoded.
+    let found = first.packages.len() + first.skipped;
+    let mut crawls = vec![(all, first)];
+    if found < total_count {
+        let categories = client
+            .get_json::<CategoryContainer>(&endpoint_for_categories(curse))
+            .await?;
+        let client = &client;
+        let rest = stream::iter(categories.data)
+            .map(|c| async move {
+                let slice = Slice {
+                    name: c.name,
+                    category_id: Some(c.id),
+                };
+                let crawl = crawl(client, curse, &slice).await?;
+                Ok::<_, Error>((slice, crawl))
+            })
+            .buffered(curse.concurrency.max(1))
+            .try_collect::<Vec<_>>()
+            .await?;
+        crawls.extend(rest);
+    }
+
+    let mut fetched = Fetched::default();
+    let mut ids: HashSet<i32> = HashSet::new();
+    for (slice, crawl) in crawls {
+        fetched.skipped += crawl.skipped;
+        fetched.partitions.push(Partition {
+            name: slice.name,
+            count: crawl.packages.len(),
+        });
+        // Projects can be part of several partitions.
+        for package in crawl.packages {
+            if ids.insert(package.id) {
+                fetched.addons.push(Addon::from(package));
+            }
+        }
+    }
+
+    if fetched.addons.len() < total_count {
+        log::warn!(
+            "{}: found {} of {} projects",
+            Source::Curse,
+            fetched.addons.len(),
+            total_count
+        );
     }
 
-    Ok(addons)
+    Ok(fetched)
+}
+
+#[test]
+fn test_versions() {
+    let package = serde_json::from_str::<Package>(
+        r#"{
+            "id": 3358,
+            "name": "Deadly Boss Mods",
+            "downloadCount": 338355764.0,
+            "summary": "Foo",
+            "links": { "websiteUrl": "https://www.curseforge.com/wow/addons/deadly-boss-mods" },
+            "authors": [{ "name": "MysticalOS" }, { "name": "Omega" }],
+            "logo": { "url": "https://example.com/logo.png" },
+            "screenshots": [{ "url": "https://example.com/1.png" }],
+            "categories": [{ "id": 1, "name": "Boss Encounters" }],
+            "latestFiles": [
+                {
+                    "id": 10,
+                    "fileDate": "2021-06-01T10:00:00Z",
+                    "releaseType": 1,
+                    "sortableGameVersions": [
+                        { "gameVersion": "9.0.5", "gameVersionTypeId": 517 },
+                        { "gameVersion": "2.5.1", "gameVersionTypeId": 73246 }
+                    ]
+                },
+                {
+                    "id": 11,
+                    "fileDate": "2021-06-02T10:00:00Z",
+                    "releaseType": 1,
+                    "dependencies": [
+                        { "modId": 1, "relationType": 3 },
+                        { "modId": 2, "relationType": 5 }
+                    ],
+                    "hashes": [
+                        { "value": "A9993E364706816ABA3E25717850C26C9CD0D89D", "algo": 1 },
+                        { "value": "900150983cd24fb0d6963f7d28e17f72", "algo": 2 }
+                    ],
+                    "fileFingerprint": 3117066732,
+                    "modules": [{ "name": "DBM-Core" }, { "name": "DBM-GUI" }],
+                    "sortableGameVersions": [
+                        { "gameVersion": "9.1.0", "gameVersionTypeId": 517 }
+                    ]
+                },
+                {
+                    "id": 12,
+                    "fileDate": "2021-06-03T10:00:00Z",
+                    "releaseType": 3,
+                    "sortableGameVersions": [
+                        { "gameVersion": "1.13.7", "gameVersionTypeId": 67408 }
+                    ]
+                }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let mut addon = Addon::from(package);
+    addon.sort_versions();
+    assert_eq!(addon.authors, vec!["MysticalOS", "Omega"]);
+    assert_eq!(
+        addon.logo_url.as_deref(),
+        Some("https://example.com/logo.png")
+    );
+    assert_eq!(addon.screenshot_urls, vec!["https://example.com/1.png"]);
+    let versions = addon
+        .versions
+        .iter()
+        .map(|v| (v.flavor, v.release_channel, v.game_version))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        versions,
+        vec![
+            (
+                Flavor::RETAIL,
+                ReleaseChannel::Stable,
+                Some(GameVersion::new(9, 1, 0))
+            ),
+            (
+                Flavor::CLASSIC_ERA,
+                ReleaseChannel::Alpha,
+                Some(GameVersion::new(1, 13, 7))
+            ),
+            (
+                Flavor::CLASSIC_TBC,
+                ReleaseChannel::Stable,
+                Some(GameVersion::new(2, 5, 1))
+            ),
+        ]
+    );
+    assert_eq!(addon.versions[0].folders, vec!["DBM-Core", "DBM-GUI"]);
+    assert_eq!(
+        addon.versions[0].dependencies,
+        vec![Dependency {
+            id: 1,
+            kind: DependencyKind::Required
+        }]
+    );
+    assert_eq!(
+        addon.versions[0].checksums,
+        Checksums {
+            md5: Some("900150983cd24fb0d6963f7d28e17f72".to_owned()),
+            sha1: Some("a9993e364706816aba3e25717850c26c9cd0d89d".to_owned()),
+            fingerprint: Some(3_117_066_732),
+        }
+    );
+}
+
+#[test]
+fn test_get_addons() {
+    use crate::backend::stand_in_server;
+
+    // Local stand-in for the CurseForge Core API with 119 projects. Project
+    // 50 shifts onto the second page while crawling.
+    let api_url = stand_in_server(|path| {
+        if path == "/v1/categories?gameId=1" {
+            return Some(r#"{ "data": [] }"#.to_owned());
+        }
+
+        let index = path
+            .split('&')
+            .find_map(|p| p.strip_prefix("index="))?
+            .parse::<usize>()
+            .ok()?;
+        let ids = match index {
+            0 => 1..51,
+            50 => 50..100,
+            100 => 100..120,
+            _ => return None,
+        };
+        let data = ids
+            .clone()
+            .map(|id| {
+                format!(
+                    r#"{{ "id": {}, "name": "", "downloadCount": 0, "summary": "", "links": {{ "websiteUrl": "" }}, "categories": [], "latestFiles": [] }}"#,
+                    id
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        Some(format!(
+            r#"{{ "data": [{}], "pagination": {{ "index": {}, "pageSize": 50, "resultCount": {}, "totalCount": 120 }} }}"#,
+            data,
+            index,
+            ids.len()
+        ))
+    });
+
+    let mut config = Config::default();
+    config.curse.api_url = api_url;
+    config.curse.api_key = Some("key".to_owned());
+    config.curse.concurrency = 2;
+
+    let fetched = futures::executor::block_on(get_addons(&config)).unwrap();
+    let ids = fetched.addons.iter().map(|a| a.id).collect::<Vec<i32>>();
+    assert_eq!(ids, (1..120).collect::<Vec<i32>>());
+    assert_eq!(fetched.partitions.len(), 1);
+    assert_eq!(fetched.partitions[0].count, 119);
+}
+
+#[test]
+fn test_get_addons_skips_categories() {
+    use crate::backend::stand_in_server;
+
+    // The whole search finds every project, one of which cannot be decoded,
+    // so categories are not requested and a request for them fails.
+    let api_url = stand_in_server(|path| {
+        if !path.starts_with("/v1/mods/search?") || path.contains("categoryId") {
+            return None;
+        }
+        Some(
+            r#"{ "data": [{ "id": 1, "name": "", "downloadCount": 0, "summary": "", "links": { "websiteUrl": "" }, "categories": [], "latestFiles": [] }, { "id": 2 }], "pagination": { "index": 0, "pageSize": 50, "resultCount": 2, "totalCount": 2 } }"#
+                .to_owned(),
+        )
+    });
+
+    let mut config = Config::default();
+    config.curse.api_url = api_url;
+    config.curse.api_key = Some("key".to_owned());
+
+    let fetched = futures::executor::block_on(get_addons(&config)).unwrap();
+    assert_eq!(fetched.addons.len(), 1);
+    assert_eq!(fetched.skipped, 1);
+    assert_eq!(fetched.partitions.len(), 1);
 }
@@ -0,0 +1,222 @@
+use chrono::{DateTime, Utc};
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::{channel_for_release_type, kind_for_relation_type};
+use crate::backend::{
+    request::Client, Addon, Checksums, Dependency, Fetched, Flavor, Source, Version,
+};
+use crate::error::Error;
+use crate::utility::{iso8601_to_datetime, Lenient};
+
+impl From<File> for Version {
+    fn from(file: File) -> Self {
+        let game_versions = file
+            .game_version
+            .iter()
+            .filter_map(|v| v.parse().ok())
+            .collect();
+        Version {
+            release_channel: channel_for_release_type(file.release_type).unwrap_or_default(),
+            download_url: file.download_url,
+            file_name: file.file_name,
+            file_size: file.file_length,
+            folders: file.modules.into_iter().map(|m| m.foldername).collect(),
+            dependencies: file
+                .dependencies
+                .iter()
+                .filter_map(|d| {
+                    kind_for_relation_type(d.r#type).map(|kind| Dependency {
+                        id: d.addon_id,
+                        kind,
+                    })
+                })
+                .collect(),
+            checksums: Checksums {
+                fingerprint: file.package_fingerprint,
+                ..Checksums::default()
+            },
+            ..Version::new(file.game_version_flavor, game_versions, file.file_date)
+        }
+    }
+}
+
+impl From<Package> for Addon {
+    fn from(package: Package) -> Self {
+        let files = package
+            .latest_files
+            .into_iter()
+            .filter(|f| channel_for_release_type(f.release_type).is_some())
+            .collect::<Vec<File>>();
+        let files_cloned = files.clone();
+        let versions = files
+            .into_iter()
+            .filter(|f| {
+                // We only want the newest for each flavor and release type.
+                !files_cloned.iter().any(|b| {
+                    b.game_version_flavor == f.game_version_flavor
+                        && b.release_type == f.release_type
+                        && b.id > f.id
+                })
+            })
+            .map(Version::from)
+            .collect();
+        Addon {
+            id: package.id,
+            source_id: None,
+            name: package.name,
+            url: package.website_url,
+            number_of_downloads: package.download_count.round() as u64,
+            summary: package.summary,
+            authors: package.authors.into_iter().map(|a| a.name).collect(),
+            version: None,
+            logo_url: package
+                .attachments
+                .iter()
+                .find(|a| a.is_default)
+                .map(|a| a.url.clone()),
+            screenshot_urls: package
+                .attachments
+                .into_iter()
+                .filter(|a| !a.is_default)
+                .map(|a| a.url)
+                .collect(),
+            versions,
+            categories: package.categories.into_iter().map(|c| c.name).collect(),
+            source: Source::Curse,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Category {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Author {
+    name: String,
+}
+
+/// An image of the addon. The default one is its logo.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Attachment {
+    url: String,
+    is_default: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct File {
+    id: i32,
+    #[serde(deserialize_with = "iso8601_to_datetime::deserialize")]
+    file_date: Option<DateTime<Utc>>,
+    game_version_flavor: Flavor,
+    game_version: Vec<String>,
+    release_type: i32,
+    #[serde(default)]
+    download_url: Option<String>,
+    #[serde(default)]
+    file_name: Option<String>,
+    #[serde(default)]
+    file_length: Option<u64>,
+    #[serde(default)]
+    modules: Vec<Module>,
+    #[serde(default)]
+    dependencies: Vec<FileDependency>,
+    #[serde(default)]
+    package_fingerprint: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FileDependency {
+    addon_id: i32,
+    r#type: i32,
+}
+
+/// An addon folder in a file.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Module {
+    foldername: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Package {
+    id: i32,
+    name: String,
+    // TODO (casperstorm): Strangely enough this expect to be `f64`.
+    // If set to u64, we get the following error:
+    // "invalid type: floating point `338355764`, expected u64"
+    download_count: f64,
+    summary: String,
+    website_url: String,
+    #[serde(default)]
+    authors: Vec<Author>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    categories: Vec<Category>,
+    latest_files: Vec<File>,
+}
+
+fn base_endpoint(api_url: &str, page_size: usize, index: usize) -> String {
+    format!(
+        "{}/api/v2/addon/search?gameId=1&pageSize={}&index={}",
+        api_url.trim_end_matches('/'),
+        page_size,
+        index
+    )
+}
+
+/// Fetches addons from the retired search API, which is still served by
+/// mirrors at `api_url`.
+///
+/// The API does not report a total, so pages are fetched in windows of
+/// `concurrency` pages until a short page is returned.
+pub async fn get_addons(
+    client: &Client,
+    api_url: &str,
+    concurrency: usize,
+) -> Result<Fetched, Error> {
+    let page_size: usize = 50;
+    let concurrency = concurrency.max(1);
+    let mut index: usize = 0;
+    let mut ids: HashSet<i32> = HashSet::new();
+    let mut fetched = Fetched::default();
+    loop {
+        let endpoints = (0..concurrency)
+            .map(|page| base_endpoint(api_url, page_size, index + page * page_size))
+            .collect::<Vec<String>>();
+        let pages = try_join_all(
+            endpoints
+                .iter()
+                .map(|endpoint| client.get_json::<Lenient<Package>>(endpoint)),
+        )
+        .await?;
+
+        let mut last_page = false;
+        for packages in pages {
+            packages.log_skipped(Source::Curse);
+            // Skipped records still count towards the page size.
+            last_page |= packages.items.len() + packages.skipped.len() < page_size;
+            fetched.skipped += packages.skipped.len();
+            // Results shift while crawling, so the same project can show up
+            // on two pages.
+            for package in packages.items {
+                if ids.insert(package.id) {
+                    fetched.addons.push(Addon::from(package));
+                }
+            }
+        }
+
+        if last_page {
+            break;
+        }
+        index += concurrency * page_size;
+    }
+
+    Ok(fetched)
+}
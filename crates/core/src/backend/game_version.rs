@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::backend::Flavor;
+use crate::error::Error;
+
+/// Table mapping major game versions to `Flavor`.
+///
+/// An entry applies to its major version and every later major version up to
+/// the next entry. The built-in table can be replaced at runtime with
+/// `load_table`, so a new retail expansion needs no rebuild.
+///
+/// Only whole major versions can be mapped, and only to an existing
+/// `GameLine`. A new classic line, eg. Cataclysm Classic on `4.4`, shares its
+/// major version with retail and needs a new `GameLine`, so it is a code
+/// change. Until then its versions are classified as retail.
+const GAME_VERSIONS: &str = include_str!("../../data/game_versions.json");
+
+static TABLE: OnceLock<Vec<Entry>> = OnceLock::new();
+
+#[derive(Deserialize, Clone, Debug)]
+struct Entry {
+    major: u32,
+    flavor: Flavor,
+}
+
+fn parse_table(json: &str) -> Result<Vec<Entry>, serde_json::Error> {
+    let mut entries: Vec<Entry> = serde_json::from_str(json)?;
+    entries.sort_by_key(|e| e.major);
+    Ok(entries)
+}
+
+fn table() -> &'static [Entry] {
+    TABLE.get_or_init(|| parse_table(GAME_VERSIONS).expect("invalid game version table"))
+}
+
+/// Replaces the built-in game version table with the one in the JSON file at
+/// `path`.
+///
+/// Has to be called before any `GameVersion::flavor`, later calls are ignored.
+pub fn load_table(path: &Path) -> Result<(), Error> {
+    let entries = parse_table(&fs::read_to_string(path)?)?;
+    if TABLE.set(entries).is_err() {
+        log::warn!(
+            "game version table is already in use, ignoring {}",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// A game client version, eg. `9.0.5`.
+///
+/// Parses from a dotted version or a TOC interface number, eg. `90005`, and
+/// serializes as a dotted version.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GameVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl GameVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        GameVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Returns the TOC interface number, eg. `9.0.5 => 90005`.
+    pub fn interface(&self) -> u32 {
+        self.major * 10_000 + self.minor * 100 + self.patch
+    }
+
+    /// Returns the `Flavor` of the live client for this version.
+    pub fn flavor(&self) -> Option<Flavor> {
+        table()
+            .iter()
+            .rev()
+            .find(|e| e.major <= self.major)
+            .map(|e| e.flavor)
+    }
+}
+
+impl fmt::Display for GameVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for GameVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("invalid game version: {:?}", s);
+
+        if s.contains('.') {
+            let mut parts = s.split('.').map(|p| p.parse::<u32>());
+            let mut next = || parts.next().transpose().map_err(|_| invalid());
+            let major = next()?.ok_or_else(invalid)?;
+            let minor = next()?.unwrap_or_default();
+            let patch = next()?.unwrap_or_default();
+            if next()?.is_some() {
+                return Err(invalid());
+            }
+            return Ok(GameVersion::new(major, minor, patch));
+        }
+
+        // Interface numbers are `major * 10000 + minor * 100 + patch`.
+        let number = s.parse::<u32>().map_err(|_| invalid())?;
+        if number >= 10_000 {
+            Ok(GameVersion::new(
+                number / 10_000,
+                number / 100 % 100,
+                number % 100,
+            ))
+        } else {
+            Ok(GameVersion::new(number, 0, 0))
+        }
+    }
+}
+
+impl Serialize for GameVersion {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for GameVersion {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::String(s) => s.parse().map_err(serde::de::Error::custom),
+            serde_json::Value::Number(num) => {
+                num.to_string().parse().map_err(serde::de::Error::custom)
+            }
+            _ => Err(serde::de::Error::custom("wrong type")),
+        }
+    }
+}
+
+#[test]
+fn test_game_version() {
+    let tests = [
+        ("9.0.5", Some(GameVersion::new(9, 0, 5))),
+        ("90005", Some(GameVersion::new(9, 0, 5))),
+        ("11307", Some(GameVersion::new(1, 13, 7))),
+        ("100002", Some(GameVersion::new(10, 0, 2))),
+        ("10.0", Some(GameVersion::new(10, 0, 0))),
+        ("9", Some(GameVersion::new(9, 0, 0))),
+        ("1.2.3.4", None),
+        ("9.x", None),
+        ("", None),
+    ];
+
+    for (version, expected) in tests.iter() {
+        assert_eq!(
+            version.parse::<GameVersion>().ok(),
+            *expected,
+            "{}",
+            version
+        );
+    }
+
+    assert_eq!(GameVersion::new(1, 13, 7).interface(), 11307);
+    assert!(GameVersion::new(10, 0, 2) > GameVersion::new(9, 1, 5));
+    assert_eq!(
+        serde_json::to_string(&GameVersion::new(9, 0, 5)).unwrap(),
+        r#""9.0.5""#
+    );
+}
+
+#[test]
+fn test_flavor() {
+    let tests = [
+        ("1.13.7", Some(Flavor::CLASSIC_ERA)),
+        ("11307", Some(Flavor::CLASSIC_ERA)),
+        ("2.5.1", Some(Flavor::CLASSIC_TBC)),
+        ("3.4.0", Some(Flavor::CLASSIC_WRATH)),
+        ("30400", Some(Flavor::CLASSIC_WRATH)),
+        ("7.3.5", Some(Flavor::RETAIL)),
+        ("9.0.5", Some(Flavor::RETAIL)),
+        ("90005", Some(Flavor::RETAIL)),
+        ("10.0.2", Some(Flavor::RETAIL)),
+        ("100002", Some(Flavor::RETAIL)),
+        ("0.1", None),
+        ("", None),
+        ("foo", None),
+    ];
+
+    for (version, flavor) in tests.iter() {
+        let parsed = version.parse::<GameVersion>().ok();
+        assert_eq!(parsed.and_then(|v| v.flavor()), *flavor, "{}", version);
+    }
+}
+
+#[test]
+fn test_parse_table() {
+    let entries = parse_table(
+        r#"[
+            { "major": 4, "flavor": "Retail" },
+            { "major": 1, "flavor": "ClassicEra" }
+        ]"#,
+    )
+    .unwrap();
+    assert_eq!(entries[0].major, 1);
+    assert_eq!(entries[1].flavor, Flavor::RETAIL);
+
+    assert!(parse_table(r#"[{ "major": 1 }]"#).is_err());
+}
@@ -0,0 +1,379 @@
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{request::Client, Addon, Fetched, Flavor, ReleaseChannel, Source, Version};
+use crate::config::{Config, GitHubConfig};
+use crate::error::Error;
+use crate::utility::{iso8601_to_datetime, null_to_default, Lenient};
+
+impl From<(Repository, Vec<Release>)> for Addon {
+    fn from(pair: (Repository, Vec<Release>)) -> Self {
+        let (repository, releases) = pair;
+        let number_of_downloads = releases
+            .iter()
+            .flat_map(|r| r.assets.iter())
+            .map(|a| a.download_count)
+            .sum();
+
+        // We want the newest published release, and the newest pre-release
+        // as beta. Releases are listed newest first.
+        let published = releases
+            .iter()
+            .filter(|r| !r.draft && r.published_at.is_some())
+            .collect::<Vec<&Release>>();
+        let stable = published.iter().find(|r| !r.prerelease);
+        let beta = published.iter().find(|r| r.prerelease);
+
+        let mut versions: Vec<Version> = vec![];
+        let channels = [
+            (ReleaseChannel::Stable, stable),
+            (ReleaseChannel::Beta, beta),
+        ];
+        for (channel, release) in channels.iter() {
+            let release = match release {
+                Some(release) => release,
+                None => continue,
+            };
+            for asset in release.assets.iter() {
+                if let Some(flavor) = flavor_for_asset(&asset.name, &release.tag_name) {
+                    // First asset wins if several match the same flavor.
+                    if !versions
+                        .iter()
+                        .any(|v| v.flavor == flavor && v.release_channel == *channel)
+                    {
+                        versions.push(Version {
+                            release_channel: *channel,
+                            download_url: Some(asset.browser_download_url.clone()),
+                            file_name: Some(asset.name.clone()),
+                            file_size: Some(asset.size),
+                            ..Version::new(flavor, vec![], release.published_at)
+                        });
+                    }
+                }
+            }
+        }
+
+        Addon {
+            id: repository.id,
+            source_id: None,
+            name: repository.name,
+            url: repository.html_url,
+            number_of_downloads,
+            summary: repository.description,
+            authors: repository
+                .full_name
+                .split('/')
+                .next()
+                .map(str::to_owned)
+                .into_iter()
+                .collect(),
+            version: stable.or(beta).map(|r| r.tag_name.clone()),
+            logo_url: None,
+            screenshot_urls: vec![],
+            versions,
+            categories: vec![],
+            source: Source::GitHub,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Repository {
+    id: i32,
+    name: String,
+    full_name: String,
+    html_url: String,
+    #[serde(deserialize_with = "null_to_default::deserialize")]
+    description: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Release {
+    tag_name: String,
+    draft: bool,
+    prerelease: bool,
+    #[serde(deserialize_with = "iso8601_to_datetime::deserialize")]
+    published_at: Option<DateTime<Utc>>,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+    size: u64,
+    download_count: u64,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct SearchResult {
+    items: Lenient<Repository>,
+}
+
+/// Returns `Flavor` for a release asset by its file name.
+///
+/// Only zip files are considered. Packagers suffix the zip with the flavor
+/// after the release tag, eg. `Foo-1.0.0-classic.zip`, using the names
+/// `Flavor` parses. A zip without a suffix is retail, and one with an
+/// unknown suffix, eg. `-nolib`, is skipped. If the tag is not part of the
+/// name, a suffix of letters only after the last dash is taken as one.
+fn flavor_for_asset(name: &str, tag: &str) -> Option<Flavor> {
+    let name = name.to_lowercase();
+    let stem = name.strip_suffix(".zip")?;
+    let tag = tag.to_lowercase();
+    let suffix = match stem.rfind(&tag).filter(|_| !tag.is_empty()) {
+        Some(index) => stem[index + tag.len()..].strip_prefix('-'),
+        None => stem
+            .rsplit_once('-')
+            .map(|(_, suffix)| suffix)
+            .filter(|suffix| suffix.chars().all(|c| c.is_ascii_alphabetic())),
+    };
+
+    match suffix {
+        Some(suffix) => suffix.parse().ok(),
+        None => Some(Flavor::RETAIL),
+    }
+}
+
+fn endpoint_for_repository(api_url: &str, full_name: &str) -> String {
+    format!("{}/repos/{}", api_url.trim_end_matches('/'), full_name)
+}
+
+fn endpoint_for_releases(api_url: &str, full_name: &str) -> String {
+    format!(
+        "{}/repos/{}/releases?per_page=20",
+        api_url.trim_end_matches('/'),
+        full_name
+    )
+}
+
+fn endpoint_for_search(api_url: &str, topic: &str, page: usize) -> String {
+    format!(
+        "{}/search/repositories?q=topic:{}&per_page={}&page={}",
+        api_url.trim_end_matches('/'),
+        topic,
+        SEARCH_PAGE_SIZE,
+        page
+    )
+}
+
+const SEARCH_PAGE_SIZE: usize = 100;
+
+/// GitHub search returns at most 1000 results.
+const SEARCH_MAX_PAGES: usize = 10;
+
+/// Returns `None` if the repository does not exist.
+async fn get_repository(
+    client: &Client,
+    config: &GitHubConfig,
+    full_name: &str,
+) -> Result<Option<Repository>, Error> {
+    let endpoint = endpoint_for_repository(&config.api_url, full_name);
+    match client.get_json::<Repository>(&endpoint).await {
+        Ok(repository) => Ok(Some(repository)),
+        Err(Error::HttpStatus { status: 404, .. }) => {
+            log::warn!("{}: repository {} not found", Source::GitHub, full_name);
+            Ok(None)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+async fn search_repositories(
+    client: &Client,
+    config: &GitHubConfig,
+    topic: &str,
+    fetched: &mut Fetched,
+) -> Result<Vec<Repository>, Error> {
+    let mut repositories = vec![];
+    for page in 1..=SEARCH_MAX_PAGES {
+        let endpoint = endpoint_for_search(&config.api_url, topic, page);
+        let result = client.get_json::<SearchResult>(&endpoint).await?;
+        result.items.log_skipped(Source::GitHub);
+        fetched.skipped += result.items.skipped.len();
+
+        let number_of_repositories = result.items.items.len() + result.items.skipped.len();
+        repositories.extend(result.items.items);
+        if number_of_repositories < SEARCH_PAGE_SIZE {
+            break;
+        }
+    }
+
+    Ok(repositories)
+}
+
+pub async fn get_addons(config: &Config) -> Result<Fetched, Error> {
+    let github = &config.github;
+    let mut client = Client::new(Source::GitHub, &github.request)
+        .header("accept", "application/vnd.github.v3+json");
+    let token = github
+        .token
+        .clone()
+        .or_else(|| std::env::var("GITHUB_TOKEN").ok());
+    if let Some(token) = token {
+        client = client.header("authorization", &format!("token {}", token));
+    }
+
+    let mut fetched = Fetched::default();
+    let mut repositories: Vec<Repository> = vec![];
+    let futures = github
+        .repositories
+        .iter()
+        .map(|full_name| get_repository(&client, github, full_name));
+    for repository in join_all(futures).await {
+        match repository? {
+            Some(repository) => repositories.push(repository),
+            None => fetched.skipped += 1,
+        }
+    }
+
+    if let Some(topic) = &github.topic {
+        let found = search_repositories(&client, github, topic, &mut fetched).await?;
+        for repository in found {
+            // Repositories can be both configured and found by topic.
+            if !repositories.iter().any(|r| r.id == repository.id) {
+                repositories.push(repository);
+            }
+        }
+    }
+
+    let client = &client;
+    let futures = repositories.iter().map(|repository| {
+        let endpoint = endpoint_for_releases(&github.api_url, &repository.full_name);
+        async move { client.get_json::<Lenient<Release>>(&endpoint).await }
+    });
+    let releases = join_all(futures).await;
+    for (repository, releases) in repositories.into_iter().zip(releases) {
+        let releases = releases?;
+        releases.log_skipped(Source::GitHub);
+        fetched.skipped += releases.skipped.len();
+
+        let addon = Addon::from((repository, releases.items));
+        // Repositories without any zip release are not addons we can offer.
+        if !addon.versions.is_empty() {
+            fetched.addons.push(addon);
+        }
+    }
+
+    Ok(fetched)
+}
+
+#[test]
+fn test_flavor_for_asset() {
+    let tests = [
+        ("Foo-1.0.0.zip", Some(Flavor::RETAIL)),
+        ("Foo-1.0.0-mainline.zip", Some(Flavor::RETAIL)),
+        ("Foo-1.0.0-classic.zip", Some(Flavor::CLASSIC_ERA)),
+        ("Foo-1.0.0-BCC.zip", Some(Flavor::CLASSIC_TBC)),
+        ("Foo-1.0.0-wrath.zip", Some(Flavor::CLASSIC_WRATH)),
+        ("Foo-1.0.0-wotlk.zip", Some(Flavor::CLASSIC_WRATH)),
+        ("Foo-1.0.0-nolib.zip", None),
+        ("Foo-1.0.0-cata.zip", None),
+        ("Foo-1.0.0.tar.gz", None),
+        // Without the tag in the name.
+        ("Foo.zip", Some(Flavor::RETAIL)),
+        ("Foo-classic.zip", Some(Flavor::CLASSIC_ERA)),
+        ("Foo-nolib.zip", None),
+        ("Foo-2.zip", Some(Flavor::RETAIL)),
+    ];
+
+    for (name, flavor) in tests.iter() {
+        assert_eq!(flavor_for_asset(name, "1.0.0"), *flavor, "{}", name);
+    }
+
+    // A tag which looks like a suffix is not taken for one.
+    assert_eq!(
+        flavor_for_asset("Foo-1.1-beta.zip", "1.1-beta"),
+        Some(Flavor::RETAIL)
+    );
+}
+
+#[test]
+fn test_get_addons() {
+    use crate::backend::{stand_in_server, Backend};
+
+    // Local stand-in for the GitHub API.
+    let api_url = stand_in_server(|path| match path {
+        "/repos/foo/bar" => Some(
+            r#"{
+                "id": 1,
+                "name": "bar",
+                "full_name": "foo/bar",
+                "html_url": "https://github.com/foo/bar",
+                "description": null
+            }"#
+            .to_owned(),
+        ),
+        "/repos/foo/bar/releases?per_page=20" => Some(
+            r#"[{
+                "tag_name": "1.1-beta",
+                "draft": false,
+                "prerelease": true,
+                "published_at": "2021-06-02T10:00:00Z",
+                "assets": [
+                    {
+                        "name": "bar-1.1-beta.zip",
+                        "browser_download_url": "https://example.com/bar-1.1-beta.zip",
+                        "size": 1024,
+                        "download_count": 0
+                    }
+                ]
+            }, {
+                "tag_name": "1.0",
+                "draft": false,
+                "prerelease": false,
+                "published_at": "2021-06-01T10:00:00Z",
+                "assets": [
+                    {
+                        "name": "bar-1.0.zip",
+                        "browser_download_url": "https://example.com/bar-1.0.zip",
+                        "size": 1024,
+                        "download_count": 10
+                    },
+                    {
+                        "name": "bar-1.0-classic.zip",
+                        "browser_download_url": "https://example.com/bar-1.0-classic.zip",
+                        "size": 1024,
+                        "download_count": 5
+                    }
+                ]
+            }]"#
+            .to_owned(),
+        ),
+        _ => None,
+    });
+
+    let mut config = Config::default();
+    config.github.api_url = api_url;
+    config.github.token = Some("token".to_owned());
+    config.github.repositories = vec!["foo/bar".to_owned(), "foo/missing".to_owned()];
+
+    let fetched = futures::executor::block_on(Source::GitHub.get_addons(&config)).unwrap();
+    assert_eq!(fetched.skipped, 1);
+    assert_eq!(fetched.addons.len(), 1);
+
+    let addon = &fetched.addons[0];
+    assert_eq!(addon.name, "bar");
+    assert_eq!(addon.number_of_downloads, 15);
+    assert_eq!(addon.authors, vec!["foo".to_owned()]);
+    assert_eq!(addon.version.as_deref(), Some("1.0"));
+    // Ordered by flavor, stable first.
+    let versions = addon
+        .versions
+        .iter()
+        .map(|v| (v.flavor, v.release_channel))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        versions,
+        vec![
+            (Flavor::RETAIL, ReleaseChannel::Stable),
+            (Flavor::RETAIL, ReleaseChannel::Beta),
+            (Flavor::CLASSIC_ERA, ReleaseChannel::Stable),
+        ]
+    );
+    assert_eq!(
+        addon.versions[2].file_name.as_deref(),
+        Some("bar-1.0-classic.zip")
+    );
+}
@@ -1,42 +1,34 @@
-use isahc::prelude::*;
-use regex::Regex;
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use futures::future::try_join_all;
 use serde::{Deserialize, Serialize};
 
-use crate::backend::{Addon, Flavor, Source, Version};
+use crate::backend::{
+    game_version, request::Client, Addon, Fetched, Flavor, GameLine, Source, Version,
+};
+use crate::config::{Config, HubConfig};
 use crate::error::Error;
-
-impl From<(GameVersion, String)> for Version {
-    fn from(pair: (GameVersion, String)) -> Self {
-        let (game_version, date) = pair;
-        Version {
-            flavor: game_version.game_type,
-            game_version: Some(game_version.interface),
-            date,
-        }
-    }
-}
+use crate::utility::{iso8601_to_datetime, normalize_text, Lenient};
 
 impl From<Package> for Addon {
     fn from(package: Package) -> Self {
-        let re = Regex::new(r"<[^>]*>").unwrap();
-        let summary = re.replace_all(&package.description, "").to_string();
-        let first_release = package.releases.first();
-        let versions: Vec<Version> = if let Some(release) = first_release {
-            release
-                .clone()
-                .game_versions
-                .into_iter()
-                .map(|gv| Version::from((gv, release.published_at.clone())))
-                .collect::<Vec<Version>>()
-        } else {
-            vec![]
-        };
+        let summary = normalize_text(&package.description);
+        let versions = package
+            .releases
+            .first()
+            .map_or(vec![], |release| release.versions());
         Addon {
             id: package.id,
+            source_id: None,
             name: package.repository_name,
             url: package.repository,
             number_of_downloads: package.total_download_count,
             summary,
+            authors: package.owner_name.into_iter().collect(),
+            version: None,
+            logo_url: None,
+            screenshot_urls: vec![],
             versions,
             categories: vec![],
             source: Source::Hub,
@@ -44,9 +36,9 @@ impl From<Package> for Addon {
     }
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Clone, Debug)]
 struct Container {
-    addons: Vec<Package>,
+    addons: Lenient<Package>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -58,33 +50,254 @@ struct GameVersion {
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 struct Release {
-    // TODO (casperstorm): Uniform all dates across sources.
-    // 2021-04-26T22:42:55.958Z
-    published_at: String,
+    #[serde(deserialize_with = "iso8601_to_datetime::deserialize")]
+    published_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    download_url: Option<String>,
     game_versions: Vec<GameVersion>,
 }
 
+impl Release {
+    /// Returns one `Version` for each flavor the release supports.
+    fn versions(&self) -> Vec<Version> {
+        let mut flavors: Vec<(Flavor, Vec<game_version::GameVersion>)> = vec![];
+        for game_version in self.game_versions.iter() {
+            let flavor = game_version.game_type;
+            let interface = game_version.interface.parse().into_iter();
+            match flavors.iter_mut().find(|(f, _)| *f == flavor) {
+                Some((_, game_versions)) => game_versions.extend(interface),
+                None => flavors.push((flavor, interface.collect())),
+            }
+        }
+
+        flavors
+            .into_iter()
+            .map(|(flavor, game_versions)| Version {
+                download_url: self.download_url.clone(),
+                ..Version::new(flavor, game_versions, self.published_at)
+            })
+            .collect()
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 struct Package {
     id: i32,
     repository: String,
     repository_name: String,
+    #[serde(default)]
+    owner_name: Option<String>,
     description: String,
     total_download_count: u64,
     releases: Vec<Release>,
 }
 
-fn base_endpoint<'a>() -> &'a str {
-    "https://hub.wowup.io/addons/featured/retail?count=1000"
+/// Number of addons requested per page.
+const PAGE_SIZE: usize = 100;
+
+/// Returns the name Hub uses for `Flavor`, or `None` if Hub has no listing
+/// for it.
+fn name_for_flavor(flavor: Flavor) -> Option<&'static str> {
+    match flavor.line {
+        GameLine::Retail => Some("retail"),
+        GameLine::ClassicEra => Some("classic"),
+        GameLine::ClassicTbc => Some("burningCrusade"),
+        _ => None,
+    }
+}
+
+fn endpoint_for_page(config: &HubConfig, name: &str, page: usize) -> String {
+    format!(
+        "{}/addons/{}?page={}&count={}",
+        config.api_url.trim_end_matches('/'),
+        name,
+        page,
+        PAGE_SIZE
+    )
+}
+
+/// Pages through every addon for `Flavor`.
+///
+/// Returns an empty list, instead of failing, if Hub has none for `Flavor`.
+/// Paging stops at the first page which is not full, or which has no addon
+/// that was not seen before.
+async fn get_packages(
+    client: &Client,
+    config: &HubConfig,
+    flavor: Flavor,
+) -> Result<Lenient<Package>, Error> {
+    let name = match name_for_flavor(flavor) {
+        Some(name) => name,
+        None => {
+            log::warn!("{}: no listing for {}", Source::Hub, flavor);
+            return Ok(Lenient::default());
+        }
+    };
+
+    let mut packages = Lenient::default();
+    let mut ids = HashSet::new();
+    let mut page = 1;
+    loop {
+        let endpoint = endpoint_for_page(config, name, page);
+        let container = client.get_json::<Container>(&endpoint).await?;
+        // Skipped records still count towards the page size.
+        let number_of_addons = container.addons.items.len() + container.addons.skipped.len();
+        let new = container
+            .addons
+            .items
+            .into_iter()
+            .filter(|package| ids.insert(package.id))
+            .collect::<Vec<Package>>();
+        let number_of_new = new.len();
+        packages.items.extend(new);
+        packages.skipped.extend(container.addons.skipped);
+
+        if number_of_addons < PAGE_SIZE {
+            break;
+        }
+        if number_of_new == 0 {
+            log::warn!(
+                "{}: page {} for {} repeats earlier addons, stopping",
+                Source::Hub,
+                page,
+                flavor
+            );
+            break;
+        }
+        page += 1;
+    }
+
+    Ok(packages)
+}
+
+pub async fn get_addons(config: &Config) -> Result<Fetched, Error> {
+    let hub = &config.hub;
+    let client = Client::new(Source::Hub, &hub.request);
+    let flavors = [Flavor::RETAIL, Flavor::CLASSIC_ERA, Flavor::CLASSIC_TBC];
+    let listings = try_join_all(
+        flavors
+            .iter()
+            .map(|flavor| get_packages(&client, hub, *flavor)),
+    )
+    .await?;
+
+    // The same addon is listed for every flavor it supports. We merge them
+    // into a single `Addon` with a `Version` for each flavor.
+    let mut fetched = Fetched::default();
+    let mut indices: HashMap<i32, usize> = HashMap::new();
+    for packages in listings {
+        packages.log_skipped(Source::Hub);
+        fetched.skipped += packages.skipped.len();
+
+        for addon in packages.items.into_iter().map(Addon::from) {
+            match indices.get(&addon.id) {
+                Some(&index) => {
+                    let existing = &mut fetched.addons[index];
+                    for version in addon.versions {
+                        if !existing.versions.iter().any(|v| v.flavor == version.flavor) {
+                            existing.versions.push(version);
+                        }
+                    }
+                }
+                None => {
+                    indices.insert(addon.id, fetched.addons.len());
+                    fetched.addons.push(addon);
+                }
+            }
+        }
+    }
+
+    Ok(fetched)
+}
+
+#[test]
+fn test_get_addons() {
+    use crate::backend::stand_in_server;
+
+    fn package(id: i32, game_type: &str) -> String {
+        format!(
+            r#"{{
+                "id": {},
+                "repository": "https://github.com/foo/bar",
+                "repository_name": "bar",
+                "owner_name": "foo",
+                "description": "<p>Foo</p>",
+                "total_download_count": 10,
+                "releases": [{{
+                    "published_at": "2021-04-26T22:42:55.958Z",
+                    "download_url": "https://example.com/bar-{}.zip",
+                    "game_versions": [{{ "game_type": "{}", "title": "", "interface": "" }}]
+                }}]
+            }}"#,
+            id, id, game_type
+        )
+    }
+
+    // Local stand-in for the Hub API.
+    let api_url = stand_in_server(|path| match path {
+        "/addons/retail?page=1&count=100" => {
+            Some(format!(r#"{{ "addons": [{}] }}"#, package(1, "retail")))
+        }
+        "/addons/classic?page=1&count=100" => Some(format!(
+            r#"{{ "addons": [{}, {}] }}"#,
+            package(1, "classic"),
+            package(2, "classic")
+        )),
+        "/addons/burningCrusade?page=1&count=100" => Some(r#"{ "addons": [] }"#.to_owned()),
+        _ => None,
+    });
+
+    let mut config = Config::default();
+    config.hub.api_url = api_url;
+
+    let fetched = futures::executor::block_on(get_addons(&config)).unwrap();
+    assert_eq!(fetched.addons.len(), 2);
+    assert_eq!(fetched.addons[0].summary, "Foo");
+    assert_eq!(fetched.addons[0].authors, vec!["foo"]);
+    let flavors = fetched.addons[0]
+        .versions
+        .iter()
+        .map(|v| v.flavor)
+        .collect::<Vec<_>>();
+    assert_eq!(flavors, vec![Flavor::RETAIL, Flavor::CLASSIC_ERA]);
+    assert_eq!(
+        fetched.addons[0].versions[0].download_url.as_deref(),
+        Some("https://example.com/bar-1.zip")
+    );
 }
 
-pub async fn get_addons() -> Result<Vec<Addon>, Error> {
-    let mut response = isahc::get_async(base_endpoint()).await?;
-    let container = response.json::<Container>().await?;
-    let addons = container
-        .addons
-        .into_iter()
-        .map(Addon::from)
-        .collect::<Vec<Addon>>();
-    Ok(addons)
+#[test]
+fn test_get_packages() {
+    use crate::backend::stand_in_server;
+
+    // Local stand-in for the Hub API which answers every page with the same
+    // full page.
+    let api_url = stand_in_server(|path| {
+        if !path.starts_with("/addons/retail?") {
+            return None;
+        }
+        let packages = (0..PAGE_SIZE as i32)
+            .map(|id| {
+                format!(
+                    r#"{{ "id": {}, "repository": "", "repository_name": "", "description": "", "total_download_count": 0, "releases": [] }}"#,
+                    id
+                )
+            })
+            .collect::<Vec<String>>();
+        Some(format!(r#"{{ "addons": [{}] }}"#, packages.join(",")))
+    });
+
+    let mut config = Config::default();
+    config.hub.api_url = api_url;
+    let client = Client::new(Source::Hub, &config.hub.request);
+
+    let packages =
+        futures::executor::block_on(get_packages(&client, &config.hub, Flavor::RETAIL)).unwrap();
+    assert_eq!(packages.items.len(), PAGE_SIZE);
+
+    // Hub has no listing for Wrath Classic, so nothing is requested.
+    let packages =
+        futures::executor::block_on(get_packages(&client, &config.hub, Flavor::CLASSIC_WRATH))
+            .unwrap();
+    assert!(packages.items.is_empty());
 }
@@ -1,107 +1,468 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::config::Config;
 use crate::error::Error;
+use crate::utility::{catalog_datetime, lenient_game_version};
 
 pub mod curse;
+pub mod game_version;
+pub mod github;
 pub mod hub;
+pub mod request;
 pub mod tukui;
+pub mod wago;
 pub mod wowinterface;
 
+pub use game_version::GameVersion;
+
 #[async_trait]
 pub trait Backend {
-    async fn get_addons(&self) -> Result<Vec<Addon>, Error>;
+    async fn get_addons(&self, config: &Config) -> Result<Fetched, Error>;
 }
 
 #[async_trait]
 impl Backend for Source {
-    async fn get_addons(&self) -> Result<Vec<Addon>, Error> {
-        match self {
-            Source::Curse => curse::get_addons().await,
-            Source::Tukui => tukui::get_addons().await,
-            Source::WowI => wowinterface::get_addons().await,
-            Source::Hub => hub::get_addons().await,
+    async fn get_addons(&self, config: &Config) -> Result<Fetched, Error> {
+        let mut fetched = match self {
+            Source::Curse => curse::get_addons(config).await,
+            Source::Tukui => tukui::get_addons(config).await,
+            Source::WowI => wowinterface::get_addons(config).await,
+            Source::Hub => hub::get_addons(config).await,
+            Source::GitHub => github::get_addons(config).await,
+            Source::Wago => wago::get_addons(config).await,
+        }?;
+        for addon in fetched.addons.iter_mut() {
+            addon.sort_versions();
         }
+        Ok(fetched)
     }
 }
 
+/// Addons fetched from a single `Source`.
+#[derive(Debug, Clone, Default)]
+pub struct Fetched {
+    pub addons: Vec<Addon>,
+    /// Number of records which could not be decoded and were skipped.
+    pub skipped: usize,
+    /// Records fetched by each part, if the source is crawled in parts.
+    pub partitions: Vec<Partition>,
+}
+
+/// Number of records fetched by one part of a partitioned crawl.
+#[derive(Debug, Clone)]
+pub struct Partition {
+    pub name: String,
+    pub count: usize,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Source {
     Curse,
     Tukui,
     WowI,
     Hub,
+    GitHub,
+    Wago,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Hash, PartialOrd, Ord)]
-pub enum Flavor {
-    #[serde(alias = "retail", alias = "wow_retail", alias = "mainline")]
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Source::Curse => "Curse",
+                Source::Tukui => "Tukui",
+                Source::WowI => "WowInterface",
+                Source::Hub => "Hub",
+                Source::GitHub => "GitHub",
+                Source::Wago => "Wago",
+            }
+        )
+    }
+}
+
+/// The game a `Flavor` belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GameLine {
     Retail,
-    RetailPtr,
-    RetailBeta,
-    #[serde(
-        alias = "classic",
-        alias = "wow_classic",
-        alias = "classic_era",
-        alias = "vanilla"
-    )]
     ClassicEra,
-    #[serde(
-        alias = "tbc",
-        alias = "bcc",
-        alias = "wow_burning_crusade",
-        alias = "burningCrusade",
-        alias = "burning_crusade"
-    )]
     ClassicTbc,
-    ClassicPtr,
-    ClassicBeta,
+    ClassicWrath,
 }
 
-impl std::fmt::Display for Flavor {
+impl GameLine {
+    const ALL: [GameLine; 4] = [
+        GameLine::Retail,
+        GameLine::ClassicEra,
+        GameLine::ClassicTbc,
+        GameLine::ClassicWrath,
+    ];
+}
+
+impl std::fmt::Display for GameLine {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
             "{}",
             match self {
-                Flavor::Retail => "retail",
-                Flavor::RetailPtr => "retail_ptr",
-                Flavor::RetailBeta => "retail_beta",
-                Flavor::ClassicEra => "classic_era",
-                Flavor::ClassicTbc => "classic_tbc",
-                Flavor::ClassicBeta => "classic_beta",
-                Flavor::ClassicPtr => "classic_ptr",
+                GameLine::Retail => "retail",
+                GameLine::ClassicEra => "classic_era",
+                GameLine::ClassicTbc => "classic_tbc",
+                GameLine::ClassicWrath => "classic_wrath",
             }
         )
     }
 }
 
+/// The client channel of a `GameLine`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ClientChannel {
+    Live,
+    Ptr,
+    Beta,
+}
+
+impl ClientChannel {
+    const ALL: [ClientChannel; 3] = [ClientChannel::Live, ClientChannel::Ptr, ClientChannel::Beta];
+}
+
+/// A game line together with a client channel.
+///
+/// Serialized as a single string, eg. `"Retail"`, `"RetailPtr"` or
+/// `"ClassicWrathBeta"`, which keeps catalogs from before the split readable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Flavor {
+    pub line: GameLine,
+    pub channel: ClientChannel,
+}
+
 impl Flavor {
-    /// Returns `Flavor` which self relates to.
+    pub const RETAIL: Flavor = Flavor::new(GameLine::Retail, ClientChannel::Live);
+    pub const RETAIL_PTR: Flavor = Flavor::new(GameLine::Retail, ClientChannel::Ptr);
+    pub const RETAIL_BETA: Flavor = Flavor::new(GameLine::Retail, ClientChannel::Beta);
+    pub const CLASSIC_ERA: Flavor = Flavor::new(GameLine::ClassicEra, ClientChannel::Live);
+    pub const CLASSIC_TBC: Flavor = Flavor::new(GameLine::ClassicTbc, ClientChannel::Live);
+    pub const CLASSIC_WRATH: Flavor = Flavor::new(GameLine::ClassicWrath, ClientChannel::Live);
+
+    pub const fn new(line: GameLine, channel: ClientChannel) -> Self {
+        Flavor { line, channel }
+    }
+
+    /// Returns the live `Flavor` of the same game line.
     pub fn base_flavor(self) -> Flavor {
-        match self {
-            Flavor::Retail | Flavor::RetailPtr | Flavor::RetailBeta => Flavor::Retail,
-            Flavor::ClassicTbc | Flavor::ClassicPtr | Flavor::ClassicBeta => Flavor::ClassicTbc,
-            Flavor::ClassicEra => Flavor::ClassicEra,
+        Flavor::new(self.line, ClientChannel::Live)
+    }
+
+    /// Returns every combination of game line and channel.
+    fn all() -> impl Iterator<Item = Flavor> {
+        GameLine::ALL.iter().flat_map(|line| {
+            ClientChannel::ALL
+                .iter()
+                .map(move |channel| Flavor::new(*line, *channel))
+        })
+    }
+
+    /// Returns the name used when serializing.
+    fn serialized_name(self) -> String {
+        match (self.line, self.channel) {
+            // Names from before the split, where classic test clients were
+            // always TBC.
+            (GameLine::ClassicTbc, ClientChannel::Ptr) => "ClassicPtr".to_owned(),
+            (GameLine::ClassicTbc, ClientChannel::Beta) => "ClassicBeta".to_owned(),
+            (line, channel) => {
+                let line = match line {
+                    GameLine::Retail => "Retail",
+                    GameLine::ClassicEra => "ClassicEra",
+                    GameLine::ClassicTbc => "ClassicTbc",
+                    GameLine::ClassicWrath => "ClassicWrath",
+                };
+                let channel = match channel {
+                    ClientChannel::Live => "",
+                    ClientChannel::Ptr => "Ptr",
+                    ClientChannel::Beta => "Beta",
+                };
+                format!("{}{}", line, channel)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Flavor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.channel) {
+            (GameLine::ClassicTbc, ClientChannel::Ptr) => write!(f, "classic_ptr"),
+            (GameLine::ClassicTbc, ClientChannel::Beta) => write!(f, "classic_beta"),
+            (line, ClientChannel::Live) => write!(f, "{}", line),
+            (line, ClientChannel::Ptr) => write!(f, "{}_ptr", line),
+            (line, ClientChannel::Beta) => write!(f, "{}_beta", line),
         }
     }
 }
 
+impl std::str::FromStr for Flavor {
+    type Err = String;
+
+    /// Parses the serialized name, the `Display` output or one of the
+    /// aliases used by sources.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let flavor = match s {
+            "retail" | "wow_retail" | "mainline" => Flavor::RETAIL,
+            "classic" | "wow_classic" | "vanilla" => Flavor::CLASSIC_ERA,
+            "tbc" | "bcc" | "wow_burning_crusade" | "burningCrusade" | "burning_crusade" => {
+                Flavor::CLASSIC_TBC
+            }
+            "wrath" | "wotlk" | "wow_wrath" => Flavor::CLASSIC_WRATH,
+            _ => Flavor::all()
+                .find(|f| f.serialized_name() == s || f.to_string() == s)
+                .ok_or_else(|| format!("unknown flavor: {}", s))?,
+        };
+        Ok(flavor)
+    }
+}
+
+impl Serialize for Flavor {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.serialized_name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Flavor {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Stability of a release, as published by its author.
+#[derive(
+    Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+pub enum ReleaseChannel {
+    #[default]
+    Stable,
+    Beta,
+    Alpha,
+}
+
+/// How a release depends on another addon.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DependencyKind {
+    Required,
+    Optional,
+    /// Shipped inside the release, nothing to install.
+    Embedded,
+    /// Only needed to develop the addon.
+    Tool,
+}
+
+/// Another addon from the same source a release depends on.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Dependency {
+    /// `Addon::id` of the dependency.
+    pub id: i32,
+    pub kind: DependencyKind,
+}
+
+/// Integrity data of a release download. Hashes are lowercase hex.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Checksums {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    /// CurseForge murmur2 fingerprint, used to match installed folders.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<u32>,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Version {
     pub flavor: Flavor,
-    pub game_version: Option<String>,
-    pub date: String,
+    /// Newest of `game_versions`. Kept for clients which only read one.
+    #[serde(default, deserialize_with = "lenient_game_version::deserialize")]
+    pub game_version: Option<GameVersion>,
+    /// Every game version the release supports for `flavor`, oldest first.
+    #[serde(default)]
+    pub game_versions: Vec<GameVersion>,
+    /// When the version was released, if the source has it.
+    #[serde(default, with = "catalog_datetime")]
+    pub date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub release_channel: ReleaseChannel,
+    /// Direct download of the release.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    /// Size of the download in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+    /// Addon folders the release installs, if the source lists them.
+    #[serde(default)]
+    pub folders: Vec<String>,
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+    #[serde(default)]
+    pub checksums: Checksums,
+}
+
+impl Version {
+    /// Creates a `Version` with `game_versions` sorted and deduplicated, and
+    /// the newest of them as `game_version`.
+    pub fn new(
+        flavor: Flavor,
+        mut game_versions: Vec<GameVersion>,
+        date: Option<DateTime<Utc>>,
+    ) -> Self {
+        game_versions.sort();
+        game_versions.dedup();
+        Version {
+            flavor,
+            game_version: game_versions.last().copied(),
+            game_versions,
+            date,
+            release_channel: ReleaseChannel::Stable,
+            download_url: None,
+            file_name: None,
+            file_size: None,
+            folders: vec![],
+            dependencies: vec![],
+            checksums: Checksums::default(),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Addon {
     pub id: i32,
+    /// Identifier used by the source, if it is not a unique number. `id` is
+    /// then derived from it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_id: Option<String>,
     pub name: String,
     pub url: String,
     pub number_of_downloads: u64,
     pub summary: String,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    /// Version string of the newest release, as named by the author.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo_url: Option<String>,
+    #[serde(default)]
+    pub screenshot_urls: Vec<String>,
+    /// One version per flavor and release channel, ordered by flavor. Within
+    /// a flavor the `Stable` version comes first, so clients which take the
+    /// first version of a flavor get a stable release if there is one.
     pub versions: Vec<Version>,
     pub categories: Vec<String>,
     pub source: Source,
 }
+
+impl Addon {
+    /// Orders `versions` by flavor, and within a flavor by release channel.
+    ///
+    /// Called for the addons of every source, so sources can collect their
+    /// versions in any order.
+    pub fn sort_versions(&mut self) {
+        self.versions.sort_by_key(|v| (v.flavor, v.release_channel));
+    }
+}
+
+/// Starts a local stand-in for a source API and returns its base URL.
+///
+/// `respond` gets the request path including the query and returns the JSON
+/// body, or `None` for a 404.
+#[cfg(test)]
+pub(crate) fn stand_in_server(respond: fn(&str) -> Option<String>) -> String {
+    stand_in_server_with_headers(move |path, _| respond(path))
+}
+
+/// Same as `stand_in_server`, but `respond` also gets the request headers,
+/// each as a `name: value` line with the name in lowercase.
+#[cfg(test)]
+pub(crate) fn stand_in_server_with_headers<F>(respond: F) -> String
+where
+    F: Fn(&str, &[String]) -> Option<String> + Send + 'static,
+{
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let api_url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut headers = vec![];
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                let (name, value) = line.trim_end().split_once(':').unwrap_or_default();
+                headers.push(format!("{}: {}", name.to_lowercase(), value.trim()));
+                line.clear();
+            }
+
+            let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = match respond(path, &headers) {
+                Some(body) => ("200 OK", body),
+                None => ("404 Not Found", r#"{"message": "Not Found"}"#.to_owned()),
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+        }
+    });
+
+    api_url
+}
+
+#[test]
+fn test_flavor_serde() {
+    // Serialized names and `Display` output from before the split.
+    let tests = [
+        ("Retail", "retail", Flavor::RETAIL),
+        ("RetailPtr", "retail_ptr", Flavor::RETAIL_PTR),
+        ("RetailBeta", "retail_beta", Flavor::RETAIL_BETA),
+        ("ClassicEra", "classic_era", Flavor::CLASSIC_ERA),
+        ("ClassicTbc", "classic_tbc", Flavor::CLASSIC_TBC),
+        (
+            "ClassicPtr",
+            "classic_ptr",
+            Flavor::new(GameLine::ClassicTbc, ClientChannel::Ptr),
+        ),
+        (
+            "ClassicBeta",
+            "classic_beta",
+            Flavor::new(GameLine::ClassicTbc, ClientChannel::Beta),
+        ),
+        (
+            "ClassicWrathPtr",
+            "classic_wrath_ptr",
+            Flavor::new(GameLine::ClassicWrath, ClientChannel::Ptr),
+        ),
+    ];
+
+    for (name, display, flavor) in tests.iter() {
+        let json = format!("\"{}\"", name);
+        assert_eq!(serde_json::from_str::<Flavor>(&json).unwrap(), *flavor);
+        assert_eq!(serde_json::to_string(flavor).unwrap(), json);
+        assert_eq!(flavor.to_string(), *display);
+        assert_eq!(display.parse::<Flavor>().unwrap(), *flavor);
+    }
+
+    for alias in ["mainline", "wow_classic", "burningCrusade", "wotlk"].iter() {
+        assert!(alias.parse::<Flavor>().is_ok());
+    }
+    assert!("foo".parse::<Flavor>().is_err());
+}
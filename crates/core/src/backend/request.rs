@@ -0,0 +1,345 @@
+use async_std::task;
+use chrono::{DateTime, Utc};
+use isahc::error::ErrorKind;
+use isahc::http::{Response, StatusCode};
+use isahc::prelude::*;
+use isahc::{AsyncBody, Request};
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::backend::Source;
+use crate::config::RequestConfig;
+use crate::error::Error;
+
+/// Shared request layer for a single `Source`.
+///
+/// Requests are rate limited according to `RequestConfig` and retried with
+/// jittered exponential backoff on transport errors, timeouts and retryable
+/// status codes. Only idempotent `GET` requests are made.
+pub struct Client {
+    source: Source,
+    config: RequestConfig,
+    headers: Vec<(String, String)>,
+    /// Earliest point in time the next request may be sent.
+    next_slot: Mutex<Instant>,
+}
+
+impl Client {
+    pub fn new(source: Source, config: &RequestConfig) -> Self {
+        Client {
+            source,
+            config: config.clone(),
+            headers: vec![],
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Adds a header which is sent with every request.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Sends a `GET` request to `url`.
+    ///
+    /// Returns `Error::HttpStatus` if the final response is not successful.
+    pub async fn get(&self, url: &str) -> Result<Response<AsyncBody>, Error> {
+        let mut attempt = 0;
+        loop {
+            self.wait_for_slot().await;
+
+            let mut request = Request::get(url);
+            for (name, value) in self.headers.iter() {
+                request = request.header(name.as_str(), value.as_str());
+            }
+            let request = request
+                .timeout(Duration::from_millis(self.config.timeout_ms))
+                .body(())
+                .map_err(|e| self.request_error(url, e.into()))?;
+            let result = isahc::send_async(request).await;
+
+            let (should_retry, retry_after) = match &result {
+                Ok(response) if is_retryable(response.status()) => (true, retry_after(response)),
+                Ok(_) => (false, None),
+                Err(error) => (is_retryable_error(error), None),
+            };
+
+            if !should_retry || attempt >= self.config.retries {
+                let response = result.map_err(|e| self.request_error(url, e))?;
+                return self.check_status(url, response).await;
+            }
+
+            let delay = self.retry_delay(attempt, retry_after);
+            match &result {
+                Ok(response) => log::warn!(
+                    "{}: {} returned {}, retrying in {:?}",
+                    self.source,
+                    url,
+                    response.status(),
+                    delay
+                ),
+                Err(error) => log::warn!(
+                    "{}: {} failed with {}, retrying in {:?}",
+                    self.source,
+                    url,
+                    error,
+                    delay
+                ),
+            }
+
+            task::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Sends a `GET` request to `url` and deserializes the response body.
+    pub async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, Error> {
+        let mut response = self.get(url).await?;
+        let mut body = vec![];
+        response
+            .copy_to(&mut body)
+            .await
+            .map_err(|e| self.request_error(url, e.into()))?;
+
+        serde_json::from_slice(&body).map_err(|inner| Error::Decode {
+            backend: self.source,
+            url: url.to_owned(),
+            inner,
+        })
+    }
+
+    /// Returns `response` if it is successful, otherwise `Error::HttpStatus`
+    /// including the beginning of the response body.
+    async fn check_status(
+        &self,
+        url: &str,
+        mut response: Response<AsyncBody>,
+    ) -> Result<Response<AsyncBody>, Error> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        Err(Error::HttpStatus {
+            backend: self.source,
+            url: url.to_owned(),
+            status: status.as_u16(),
+            body_snippet: body_snippet(&body),
+        })
+    }
+
+    fn request_error(&self, url: &str, inner: isahc::Error) -> Error {
+        Error::Request {
+            backend: self.source,
+            url: url.to_owned(),
+            inner,
+        }
+    }
+
+    /// Waits until the rate limit allows another request.
+    async fn wait_for_slot(&self) {
+        let requests_per_second = match self.config.requests_per_second {
+            Some(rps) if rps > 0.0 => rps,
+            _ => return,
+        };
+
+        let interval = Duration::from_secs_f64(1.0 / requests_per_second);
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let slot = (*next_slot).max(Instant::now());
+            *next_slot = slot + interval;
+            slot
+        };
+
+        let now = Instant::now();
+        if slot > now {
+            task::sleep(slot - now).await;
+        }
+    }
+
+    /// Returns a jittered exponential backoff delay for `attempt`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let max = self.config.max_backoff_ms;
+        let delay = self
+            .config
+            .backoff_ms
+            .saturating_mul(1 << attempt.min(16))
+            .min(max);
+        // Pick a delay between half and the full backoff, so concurrent
+        // requests do not retry in lockstep.
+        let jittered = rand::thread_rng().gen_range(delay / 2..=delay);
+        Duration::from_millis(jittered)
+    }
+
+    /// Returns the delay before retrying `attempt`. A delay asked for with
+    /// `Retry-After` is used, but capped to `max_backoff_ms` so a long one
+    /// does not stall the run.
+    fn retry_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        match retry_after {
+            Some(delay) => delay.min(Duration::from_millis(self.config.max_backoff_ms)),
+            None => self.backoff(attempt),
+        }
+    }
+}
+
+/// Returns `true` if a request answered with `status` is worth retrying.
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::BAD_GATEWAY
+        || status == StatusCode::SERVICE_UNAVAILABLE
+        || status == StatusCode::GATEWAY_TIMEOUT
+        || status == StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Returns `true` if a request which failed with `error` is worth retrying.
+fn is_retryable_error(error: &isahc::Error) -> bool {
+    error.is_network() || error.is_server() || *error.kind() == ErrorKind::Timeout
+}
+
+/// Returns the first characters of `body` on a single line.
+fn body_snippet(body: &str) -> String {
+    const MAX_CHARS: usize = 200;
+    let body = body.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if body.chars().count() > MAX_CHARS {
+        format!("{}…", body.chars().take(MAX_CHARS).collect::<String>())
+    } else {
+        body
+    }
+}
+
+/// Returns the delay from a `Retry-After` header.
+fn retry_after(response: &Response<AsyncBody>) -> Option<Duration> {
+    let value = response.headers().get("retry-after")?.to_str().ok()?;
+    parse_retry_after(value, Utc::now())
+}
+
+/// Parses a `Retry-After` value given either in seconds or as an HTTP date.
+/// A date in the past is no delay.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[test]
+fn test_backoff() {
+    let config = RequestConfig {
+        backoff_ms: 100,
+        max_backoff_ms: 1_000,
+        ..RequestConfig::default()
+    };
+    let client = Client::new(Source::Curse, &config);
+
+    for (attempt, max) in [(0, 100), (1, 200), (3, 800), (4, 1_000), (40, 1_000)].iter() {
+        let delay = client.backoff(*attempt).as_millis() as u64;
+        assert!(delay >= max / 2 && delay <= *max);
+    }
+}
+
+#[test]
+fn test_body_snippet() {
+    assert_eq!(
+        body_snippet("<html>\n  <body>Bad Gateway</body>\n</html>"),
+        "<html> <body>Bad Gateway</body> </html>"
+    );
+
+    let snippet = body_snippet(&"a".repeat(500));
+    assert_eq!(snippet.chars().count(), 201);
+    assert!(snippet.ends_with('…'));
+}
+
+#[test]
+fn test_retry_delay() {
+    let config = RequestConfig {
+        max_backoff_ms: 30_000,
+        ..RequestConfig::default()
+    };
+    let client = Client::new(Source::Curse, &config);
+
+    assert_eq!(
+        client.retry_delay(0, Some(Duration::from_secs(5))),
+        Duration::from_secs(5)
+    );
+    assert_eq!(
+        client.retry_delay(0, Some(Duration::from_secs(86_400))),
+        Duration::from_secs(30)
+    );
+}
+
+#[test]
+fn test_parse_retry_after() {
+    let now = DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z")
+        .unwrap()
+        .with_timezone(&Utc);
+    let tests = [
+        ("120", Some(Duration::from_secs(120))),
+        (
+            "Wed, 21 Oct 2015 07:29:30 GMT",
+            Some(Duration::from_secs(90)),
+        ),
+        (
+            "Wed, 21 Oct 2015 07:00:00 GMT",
+            Some(Duration::from_secs(0)),
+        ),
+        ("soon", None),
+    ];
+
+    for (value, delay) in tests.iter() {
+        assert_eq!(parse_retry_after(value, now), *delay, "{}", value);
+    }
+}
+
+#[test]
+fn test_retry_timeout() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    // Local server which stalls on the first request and answers the next.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let mut stalled = vec![];
+        for (index, stream) in listener.incoming().enumerate() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+
+            if index == 0 {
+                // Keep the connection open without answering.
+                stalled.push(stream);
+                continue;
+            }
+            let body = "[1]";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    let config = RequestConfig {
+        retries: 1,
+        backoff_ms: 1,
+        timeout_ms: 200,
+        ..RequestConfig::default()
+    };
+    let client = Client::new(Source::Curse, &config);
+    let body = futures::executor::block_on(client.get_json::<Vec<i32>>(&url)).unwrap();
+    assert_eq!(body, vec![1]);
+}
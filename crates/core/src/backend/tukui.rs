@@ -1,24 +1,44 @@
-use futures::try_join;
-use isahc::prelude::*;
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use futures::{future::try_join_all, try_join};
 use serde::{Deserialize, Serialize};
 
-use crate::backend::{Addon, Flavor, Source, Version};
+use crate::backend::{request::Client, Addon, Fetched, Flavor, GameLine, Source, Version};
+use crate::config::Config;
 use crate::error::Error;
-use crate::utility::{null_to_default, number_and_string_to_i32, number_and_string_to_u64};
+use crate::utility::{
+    date_to_datetime, null_to_default, number_and_string_to_i32, number_and_string_to_u64,
+    stable_id, Lenient,
+};
 
 impl From<(Package, Flavor)> for Addon {
     fn from(pair: (Package, Flavor)) -> Self {
         let (package, flavor) = pair;
         Addon {
             id: package.id,
+            source_id: None,
             name: package.name,
             url: package.web_url,
             number_of_downloads: package.downloads,
             summary: package.small_desc,
+            authors: Some(package.author)
+                .filter(|a| !a.is_empty())
+                .into_iter()
+                .collect(),
+            version: Some(package.version).filter(|v| !v.is_empty()),
+            logo_url: None,
+            screenshot_urls: Some(package.screenshot_url)
+                .filter(|s| !s.is_empty())
+                .into_iter()
+                .collect(),
             versions: vec![Version {
-                flavor,
-                game_version: Some(package.patch),
-                date: package.lastupdate,
+                download_url: Some(package.url).filter(|url| !url.is_empty()),
+                ..Version::new(
+                    flavor,
+                    package.patch.parse().into_iter().collect(),
+                    package.lastupdate,
+                )
             }],
             categories: vec![package.category],
             source: Source::Tukui,
@@ -46,8 +66,8 @@ struct Package {
     category: String,
     #[serde(deserialize_with = "number_and_string_to_u64::deserialize")]
     downloads: u64,
-    #[serde(deserialize_with = "null_to_default::deserialize")]
-    lastupdate: String,
+    #[serde(deserialize_with = "date_to_datetime::deserialize")]
+    lastupdate: Option<DateTime<Utc>>,
     #[serde(deserialize_with = "null_to_default::deserialize")]
     patch: String,
     #[serde(deserialize_with = "null_to_default::deserialize")]
@@ -58,82 +78,158 @@ fn base_endpoint<'a>() -> &'a str {
     "https://www.tukui.org/api.php"
 }
 
-fn endpoint_for_addons(flavor: &Flavor) -> String {
+/// Returns the endpoint of the addon list for `Flavor`, or `None` if Tukui
+/// has no list for it.
+fn endpoint_for_addons(flavor: &Flavor) -> Option<String> {
     let base_endpoint = base_endpoint();
-    match flavor.base_flavor() {
-        Flavor::Retail => {
-            format!("{}?addons=all", base_endpoint)
-        }
-        Flavor::ClassicEra => {
-            format!("{}?classic-addons=all", base_endpoint)
-        }
-        Flavor::ClassicTbc => {
-            format!("{}?classic-tbc-addons=all", base_endpoint)
-        }
-        _ => panic!("Unknown base flavor {}", flavor),
+    match flavor.line {
+        GameLine::Retail => Some(format!("{}?addons=all", base_endpoint)),
+        GameLine::ClassicEra => Some(format!("{}?classic-addons=all", base_endpoint)),
+        GameLine::ClassicTbc => Some(format!("{}?classic-tbc-addons=all", base_endpoint)),
+        _ => None,
     }
 }
 
-fn endpoint_for_tukui() -> String {
-    format!("{}?ui=tukui", base_endpoint())
+/// Returns endpoints for the two flagship UIs; ElvUI & Tukui, for `Flavor`.
+///
+/// They are not part of the addon lists and have to be fetched on their own.
+/// On classic flavors ElvUI has id `2` and Tukui id `1`. Empty if Tukui has
+/// no list for `Flavor`.
+fn endpoints_for_flagships(flavor: &Flavor) -> Vec<String> {
+    let base_endpoint = base_endpoint();
+    match flavor.line {
+        GameLine::Retail => vec![
+            format!("{}?ui=elvui", base_endpoint),
+            format!("{}?ui=tukui", base_endpoint),
+        ],
+        GameLine::ClassicEra => vec![
+            format!("{}?classic-addon=2", base_endpoint),
+            format!("{}?classic-addon=1", base_endpoint),
+        ],
+        GameLine::ClassicTbc => vec![
+            format!("{}?classic-tbc-addon=2", base_endpoint),
+            format!("{}?classic-tbc-addon=1", base_endpoint),
+        ],
+        _ => vec![],
+    }
 }
 
-fn endpoint_for_elvui() -> String {
-    format!("{}?ui=elvui", base_endpoint())
+/// Fetches the addon list and the flagship UIs for `Flavor`.
+///
+/// Returns an empty list, instead of failing, if Tukui has none for `Flavor`.
+async fn get_packages(client: &Client, flavor: &Flavor) -> Result<Lenient<Package>, Error> {
+    let all_endpoint = match endpoint_for_addons(flavor) {
+        Some(endpoint) => endpoint,
+        None => {
+            log::warn!("{}: no addon list for {}", Source::Tukui, flavor);
+            return Ok(Lenient::default());
+        }
+    };
+    let flagship_endpoints = endpoints_for_flagships(flavor);
+
+    let all_future = client.get_json::<Lenient<Package>>(&all_endpoint);
+    let flagship_futures = try_join_all(
+        flagship_endpoints
+            .iter()
+            .map(|endpoint| client.get_json::<Package>(endpoint)),
+    );
+
+    let (mut packages, flagships) = try_join!(all_future, flagship_futures)?;
+    packages.items.extend(flagships);
+    Ok(packages)
 }
 
-pub async fn get_addons() -> Result<Vec<Addon>, Error> {
-    let flavors = vec![Flavor::Retail, Flavor::ClassicEra, Flavor::ClassicTbc];
-    let mut addons: Vec<Addon> = vec![];
-    for flavor in flavors.iter() {
-        match flavor.base_flavor() {
-            // When fetching retail AddOns, we have to get the two main addons;
-            // Elvui & Tukui from two seperate endpoints, and then combine with
-            // the rest.
-            Flavor::Retail => {
-                let elv_res_future = isahc::get_async(endpoint_for_elvui());
-                let tuk_res_future = isahc::get_async(endpoint_for_tukui());
-                let all_res_future = isahc::get_async(endpoint_for_addons(&flavor));
-
-                let (mut elv_res, mut tuk_res, mut all_res) =
-                    try_join!(elv_res_future, tuk_res_future, all_res_future)?;
-
-                let elv_json_future = elv_res.json::<Package>();
-                let tuk_json_future = tuk_res.json::<Package>();
-                let all_json_future = all_res.json::<Vec<Package>>();
-
-                let (elv_package, tuk_package, all_packages) =
-                    try_join!(elv_json_future, tuk_json_future, all_json_future)?;
-
-                let mut packages = vec![];
-                packages.extend(all_packages);
-                packages.push(elv_package);
-                packages.push(tuk_package);
-
-                // Extends addons with `Package` converted to `Addon`.
-                addons.extend(
-                    packages
-                        .into_iter()
-                        .map(|package| Addon::from((package, *flavor)))
-                        .collect::<Vec<Addon>>(),
-                );
-            }
-            _ => {
-                let mut response = isahc::get_async(endpoint_for_addons(&flavor)).await?;
-                let packages = response.json::<Vec<Package>>().await?;
-
-                // Extends addons with `Package` converted to `Addon`.
-                addons.extend(
-                    packages
-                        .into_iter()
-                        .map(|package| Addon::from((package, *flavor)))
-                        .collect::<Vec<Addon>>(),
-                );
+/// Returns the key which identifies a project across flavors.
+///
+/// Each flavor has its own list with its own ids, so the id cannot be used.
+/// Entries with the same name and author are the same project.
+fn project_key(package: &Package) -> (String, String) {
+    (
+        package.name.trim().to_lowercase(),
+        package.author.trim().to_lowercase(),
+    )
+}
+
+/// Merges the lists of each flavor into one `Addon` per project.
+///
+/// A project can show up more than once in a flavor, eg. a flagship UI which
+/// is fetched on its own and also listed, so only its first entry in each
+/// flavor is used. Each flavor has its own ids, so a project keeps the id of
+/// its entry with the lowest flavor, retail before classic era before tbc,
+/// which is the id its retail installs already carry. If that id is shared by
+/// several projects, the one with the lowest flavor keeps it. The others qualify it
+/// with their flavor, eg. `classic_era-12`, kept in `Addon::source_id`, and
+/// `Addon::id` is derived from it. The result does not depend on the order of
+/// `listings`.
+fn merge(listings: Vec<(Flavor, Vec<Package>)>) -> Vec<Addon> {
+    let mut indices: HashMap<(String, String), usize> = HashMap::new();
+    let mut projects: Vec<(_, Vec<(Flavor, Package)>)> = vec![];
+    for (flavor, packages) in listings {
+        for package in packages {
+            let key = project_key(&package);
+            match indices.get(&key) {
+                Some(&index) => {
+                    let entries = &mut projects[index].1;
+                    if !entries.iter().any(|(f, _)| *f == flavor) {
+                        entries.push((flavor, package));
+                    }
+                }
+                None => {
+                    indices.insert(key.clone(), projects.len());
+                    projects.push((key, vec![(flavor, package)]));
+                }
             }
         }
     }
 
-    Ok(addons)
+    let mut projects = projects
+        .into_iter()
+        .map(|(key, mut entries)| {
+            entries.sort_by_key(|(flavor, _)| *flavor);
+            let (flavor, package) = &entries[0];
+            ((package.id, *flavor), key, entries)
+        })
+        .collect::<Vec<_>>();
+    projects.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+    let mut addons: Vec<Addon> = vec![];
+    let mut previous_id = None;
+    for ((id, flavor), _, entries) in projects {
+        let mut entries = entries.into_iter().map(|(f, p)| Addon::from((p, f)));
+        let mut addon = entries.next().unwrap();
+        for other in entries {
+            addon.number_of_downloads += other.number_of_downloads;
+            addon.versions.extend(other.versions);
+        }
+
+        addon.id = id;
+        if previous_id == Some(id) {
+            let source_id = format!("{}-{}", flavor, id);
+            addon.id = stable_id(&source_id);
+            addon.source_id = Some(source_id);
+        }
+        previous_id = Some(id);
+        addons.push(addon);
+    }
+
+    addons
+}
+
+pub async fn get_addons(config: &Config) -> Result<Fetched, Error> {
+    let client = Client::new(Source::Tukui, config.request(Source::Tukui));
+    let flavors = [Flavor::RETAIL, Flavor::CLASSIC_ERA, Flavor::CLASSIC_TBC];
+    let listings = try_join_all(flavors.iter().map(|flavor| get_packages(&client, flavor))).await?;
+
+    let mut fetched = Fetched::default();
+    let mut merged = vec![];
+    for (flavor, packages) in flavors.iter().zip(listings) {
+        packages.log_skipped(Source::Tukui);
+        fetched.skipped += packages.skipped.len();
+        merged.push((*flavor, packages.items));
+    }
+
+    fetched.addons = merge(merged);
+    Ok(fetched)
 }
 
 #[test]
@@ -141,7 +237,7 @@ fn test_null_fields() {
     let tests = [
         r"[]",
         r#"[{
-            "id": null,
+            "id": 12,
             "name": null,
             "small_desc": null,
             "author": null,
@@ -160,4 +256,105 @@ fn test_null_fields() {
     for test in tests.iter() {
         serde_json::from_str::<Vec<Package>>(test).unwrap();
     }
+
+    // Without an id the record is skipped, instead of sharing id `0`.
+    let packages = serde_json::from_str::<Lenient<Package>>(
+        r#"[{
+            "id": null,
+            "name": "Foo",
+            "small_desc": "",
+            "author": "",
+            "version": "",
+            "screenshot_url": "",
+            "web_url": "",
+            "url": "",
+            "category": "",
+            "downloads": 0,
+            "lastupdate": null,
+            "patch": ""
+        }]"#,
+    )
+    .unwrap();
+    assert_eq!(packages.items.len(), 0);
+    assert_eq!(packages.skipped.len(), 1);
+}
+
+#[test]
+fn test_merge() {
+    fn package(id: i32, name: &str, author: &str) -> Package {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": name,
+            "small_desc": "",
+            "author": author,
+            "version": "1.0",
+            "screenshot_url": "",
+            "url": "",
+            "category": "",
+            "downloads": 10,
+            "lastupdate": "2021-06-01",
+            "patch": "",
+            "web_url": ""
+        }))
+        .unwrap()
+    }
+
+    let listings = vec![
+        (
+            Flavor::RETAIL,
+            vec![
+                package(-2, "ElvUI", "Elv"),
+                package(12, "Foo", "Bar"),
+                // Flagship UI which is also listed.
+                package(-2, "ElvUI", "Elv"),
+                package(400, "Quux", "Bar"),
+            ],
+        ),
+        (
+            Flavor::CLASSIC_ERA,
+            vec![
+                package(2, "ElvUI", "Elv"),
+                package(12, "Baz", "Qux"),
+                package(13, "Quux", "Bar"),
+            ],
+        ),
+    ];
+
+    let addons = merge(listings.clone());
+    assert_eq!(addons.len(), 4);
+
+    // Same project in both lists, counted once for each flavor. It keeps the
+    // retail id.
+    assert_eq!(addons[0].id, -2);
+    assert_eq!(addons[0].number_of_downloads, 20);
+    assert_eq!(addons[0].versions.len(), 2);
+
+    // Different projects sharing id 12.
+    assert_eq!(addons[1].name, "Foo");
+    assert_eq!(addons[1].id, 12);
+    assert_eq!(addons[2].name, "Baz");
+    assert_eq!(addons[2].source_id.as_deref(), Some("classic_era-12"));
+    assert_eq!(addons[2].id, stable_id("classic_era-12"));
+
+    // The retail id is kept, even though the classic id is lower.
+    assert_eq!(addons[3].name, "Quux");
+    assert_eq!(addons[3].id, 400);
+    assert_eq!(addons[3].versions.len(), 2);
+
+    // The order of the lists does not matter.
+    let reversed = merge(listings.into_iter().rev().collect());
+    let ids = |addons: &[Addon]| addons.iter().map(|a| a.id).collect::<Vec<i32>>();
+    assert_eq!(ids(&reversed), ids(&addons));
+}
+
+#[test]
+fn test_unsupported_flavor() {
+    assert_eq!(endpoint_for_addons(&Flavor::CLASSIC_WRATH), None);
+    assert!(endpoints_for_flagships(&Flavor::CLASSIC_WRATH).is_empty());
+
+    let config = crate::config::RequestConfig::default();
+    let client = Client::new(Source::Tukui, &config);
+    let packages =
+        futures::executor::block_on(get_packages(&client, &Flavor::CLASSIC_WRATH)).unwrap();
+    assert!(packages.items.is_empty());
 }
@@ -0,0 +1,386 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{request::Client, Addon, Fetched, Flavor, ReleaseChannel, Source, Version};
+use crate::catalog;
+use crate::config::{Config, WagoConfig};
+use crate::error::Error;
+use crate::utility::{
+    iso8601_to_datetime, null_to_default, number_and_string_to_u64, stable_id, Lenient,
+};
+
+impl From<Package> for Addon {
+    fn from(package: Package) -> Self {
+        let recent = package.recent_release;
+        let releases = [
+            (ReleaseChannel::Stable, recent.stable),
+            (ReleaseChannel::Beta, recent.beta),
+            (ReleaseChannel::Alpha, recent.alpha),
+        ];
+
+        // One version for each flavor a channel's release supports.
+        let mut versions: Vec<Version> = vec![];
+        for flavor in [Flavor::RETAIL, Flavor::CLASSIC_ERA, Flavor::CLASSIC_TBC].iter() {
+            for (channel, release) in releases.iter() {
+                let release = match release {
+                    Some(release) => release,
+                    None => continue,
+                };
+                if let Some(patch) = release.patch_for_flavor(*flavor) {
+                    let game_versions = patch.parse().into_iter().collect();
+                    versions.push(Version {
+                        release_channel: *channel,
+                        ..Version::new(*flavor, game_versions, release.created_at)
+                    });
+                }
+            }
+        }
+
+        Addon {
+            id: stable_id(&package.id),
+            source_id: Some(package.id),
+            name: package.display_name,
+            url: package.website_url,
+            number_of_downloads: package.download_count,
+            summary: package.summary,
+            authors: package.authors,
+            version: releases
+                .iter()
+                .find_map(|(_, release)| release.as_ref())
+                .map(|release| release.label.clone()),
+            logo_url: package.thumbnail_image,
+            screenshot_urls: vec![],
+            versions,
+            categories: package.categories,
+            source: Source::Wago,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct Container {
+    data: Lenient<Package>,
+    meta: Meta,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Meta {
+    current_page: usize,
+    last_page: usize,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Package {
+    id: String,
+    display_name: String,
+    #[serde(deserialize_with = "null_to_default::deserialize")]
+    summary: String,
+    #[serde(deserialize_with = "null_to_default::deserialize")]
+    website_url: String,
+    #[serde(deserialize_with = "number_and_string_to_u64::deserialize")]
+    download_count: u64,
+    #[serde(default, deserialize_with = "null_to_default::deserialize")]
+    authors: Vec<String>,
+    #[serde(default)]
+    thumbnail_image: Option<String>,
+    #[serde(deserialize_with = "null_to_default::deserialize")]
+    categories: Vec<String>,
+    recent_release: RecentReleases,
+}
+
+/// Newest release for each stability.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct RecentReleases {
+    stable: Option<Release>,
+    beta: Option<Release>,
+    alpha: Option<Release>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Release {
+    label: String,
+    #[serde(deserialize_with = "iso8601_to_datetime::deserialize")]
+    created_at: Option<DateTime<Utc>>,
+    supported_retail_patch: Option<String>,
+    supported_classic_patch: Option<String>,
+    supported_bc_patch: Option<String>,
+}
+
+impl Release {
+    /// Returns the supported patch for `Flavor`, if the release supports it.
+    fn patch_for_flavor(&self, flavor: Flavor) -> Option<&str> {
+        let patch = match flavor {
+            Flavor::RETAIL => &self.supported_retail_patch,
+            Flavor::CLASSIC_ERA => &self.supported_classic_patch,
+            Flavor::CLASSIC_TBC => &self.supported_bc_patch,
+            _ => &None,
+        };
+        patch.as_deref().filter(|p| !p.is_empty())
+    }
+}
+
+fn endpoint_for_page(config: &WagoConfig, page: usize) -> String {
+    format!(
+        "{}/addons?page={}",
+        config.api_url.trim_end_matches('/'),
+        page
+    )
+}
+
+pub async fn get_addons(config: &Config) -> Result<Fetched, Error> {
+    let wago = &config.wago;
+    let api_key = wago.api_key().ok_or(Error::MissingApiKey {
+        backend: Source::Wago,
+    })?;
+    let client = Client::new(Source::Wago, &wago.request)
+        .header("authorization", &format!("Bearer {}", api_key));
+
+    let mut fetched = Fetched::default();
+    let mut page = 1;
+    loop {
+        let endpoint = endpoint_for_page(wago, page);
+        let container = client.get_json::<Container>(&endpoint).await?;
+        container.data.log_skipped(Source::Wago);
+        fetched.skipped += container.data.skipped.len();
+        fetched
+            .addons
+            .extend(container.data.items.into_iter().map(Addon::from));
+
+        if container.meta.current_page >= container.meta.last_page {
+            break;
+        }
+        page += 1;
+    }
+
+    let previous = wago
+        .previous_catalog
+        .as_deref()
+        .map_or_else(HashMap::new, previous_ids);
+    assign_ids(&mut fetched.addons, &previous);
+    Ok(fetched)
+}
+
+/// Returns ids by `source_id` of the Wago addons in a previous catalog.
+fn previous_ids(path: &Path) -> HashMap<String, i32> {
+    catalog::load(path)
+        .into_iter()
+        .filter(|addon| addon.source == Source::Wago)
+        .filter_map(|addon| Some((addon.source_id?, addon.id)))
+        .collect()
+}
+
+/// Gives every addon a distinct id.
+///
+/// Ids are hashed from Wago's string ids, so two addons can collide. An addon
+/// keeps its id from `previous`, so a new addon never takes over the id of an
+/// existing one. The other addons are visited in `source_id` order and a
+/// colliding id is re-hashed with a counter, which keeps the outcome
+/// independent of the order pages arrive in.
+fn assign_ids(addons: &mut [Addon], previous: &HashMap<String, i32>) {
+    addons.sort_by(|a, b| a.source_id.cmp(&b.source_id));
+
+    let previous_id = |addon: &Addon| {
+        addon
+            .source_id
+            .as_ref()
+            .and_then(|source_id| previous.get(source_id))
+            .copied()
+    };
+    let mut taken = HashSet::new();
+    for addon in addons.iter_mut() {
+        if let Some(id) = previous_id(addon) {
+            addon.id = id;
+            taken.insert(id);
+        }
+    }
+
+    for addon in addons.iter_mut().filter(|a| previous_id(a).is_none()) {
+        let source_id = addon.source_id.clone().unwrap_or_default();
+        let mut salt = 0;
+        while !taken.insert(addon.id) {
+            salt += 1;
+            let id = stable_id(&format!("{}-{}", source_id, salt));
+            log::warn!(
+                "{}: id {} of {} is taken, using {}",
+                Source::Wago,
+                addon.id,
+                source_id,
+                id
+            );
+            addon.id = id;
+        }
+    }
+}
+
+#[test]
+fn test_versions() {
+    use crate::backend::GameVersion;
+
+    let package = serde_json::from_str::<Package>(
+        r#"{
+            "id": "aNDmy96o",
+            "display_name": "Foo",
+            "summary": null,
+            "website_url": "https://addons.wago.io/addons/foo",
+            "download_count": "1200",
+            "categories": null,
+            "recent_release": {
+                "stable": {
+                    "label": "1.0.0",
+                    "created_at": "2021-06-01T10:00:00Z",
+                    "supported_retail_patch": "9.0.5",
+                    "supported_classic_patch": null,
+                    "supported_bc_patch": null
+                },
+                "beta": {
+                    "label": "1.1.0-beta",
+                    "created_at": "2021-06-02T10:00:00Z",
+                    "supported_retail_patch": "9.1.0",
+                    "supported_classic_patch": null,
+                    "supported_bc_patch": "2.5.1"
+                },
+                "alpha": null
+            }
+        }"#,
+    )
+    .unwrap();
+
+    let addon = Addon::from(package);
+    assert_eq!(addon.source_id.as_deref(), Some("aNDmy96o"));
+    let versions = addon
+        .versions
+        .iter()
+        .map(|v| (v.flavor, v.release_channel, v.game_version))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        versions,
+        vec![
+            (
+                Flavor::RETAIL,
+                ReleaseChannel::Stable,
+                Some(GameVersion::new(9, 0, 5))
+            ),
+            (
+                Flavor::RETAIL,
+                ReleaseChannel::Beta,
+                Some(GameVersion::new(9, 1, 0))
+            ),
+            (
+                Flavor::CLASSIC_TBC,
+                ReleaseChannel::Beta,
+                Some(GameVersion::new(2, 5, 1))
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_assign_ids() {
+    let addon = |source_id: &str, id: i32| Addon {
+        id,
+        source_id: Some(source_id.to_string()),
+        name: source_id.to_string(),
+        url: String::new(),
+        number_of_downloads: 0,
+        summary: String::new(),
+        authors: vec![],
+        version: None,
+        logo_url: None,
+        screenshot_urls: vec![],
+        versions: vec![],
+        categories: vec![],
+        source: Source::Wago,
+    };
+
+    // Pretend "b" and "a" hash to the same id.
+    let mut addons = vec![addon("b", 7), addon("c", 9), addon("a", 7)];
+    let ids = |addons: &[Addon]| {
+        addons
+            .iter()
+            .map(|a| (a.source_id.clone().unwrap(), a.id))
+            .collect::<Vec<_>>()
+    };
+
+    let mut fresh = addons.clone();
+    assign_ids(&mut fresh, &HashMap::new());
+    assert_eq!(
+        ids(&fresh),
+        vec![
+            ("a".to_owned(), 7),
+            ("b".to_owned(), stable_id("b-1")),
+            ("c".to_owned(), 9)
+        ]
+    );
+
+    // "b" had the id before "a" was added, so it keeps it.
+    let previous = vec![("b".to_owned(), 7)].into_iter().collect();
+    assign_ids(&mut addons, &previous);
+    assert_eq!(
+        ids(&addons),
+        vec![
+            ("a".to_owned(), stable_id("a-1")),
+            ("b".to_owned(), 7),
+            ("c".to_owned(), 9)
+        ]
+    );
+}
+
+#[test]
+fn test_get_addons() {
+    use crate::backend::stand_in_server_with_headers;
+
+    fn package(id: &str) -> String {
+        format!(
+            r#"{{
+                "id": "{}",
+                "display_name": "Foo",
+                "summary": "",
+                "website_url": "",
+                "download_count": 1,
+                "categories": [],
+                "recent_release": {{ "stable": null, "beta": null, "alpha": null }}
+            }}"#,
+            id
+        )
+    }
+
+    // Local stand-in for the Wago API with two pages, which only answers
+    // requests with the API key.
+    let api_url = stand_in_server_with_headers(|path, headers| {
+        if !headers.iter().any(|h| h == "authorization: Bearer key") {
+            return None;
+        }
+        let (data, current_page) = match path {
+            "/addons?page=1" => (package("a"), 1),
+            "/addons?page=2" => (format!("{}, {}", package("b"), r#"{ "id": "c" }"#), 2),
+            _ => return None,
+        };
+        Some(format!(
+            r#"{{ "data": [{}], "meta": {{ "current_page": {}, "last_page": 2 }} }}"#,
+            data, current_page
+        ))
+    });
+
+    let mut config = Config::default();
+    config.wago.api_url = api_url;
+    config.wago.api_key = Some("key".to_owned());
+
+    let fetched = futures::executor::block_on(get_addons(&config)).unwrap();
+    assert_eq!(fetched.skipped, 1);
+    let source_ids = fetched
+        .addons
+        .iter()
+        .map(|a| a.source_id.as_deref().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(source_ids, vec!["a", "b"]);
+
+    // Without a key nothing is requested.
+    if std::env::var("WAGO_API_KEY").is_err() {
+        config.wago.api_key = None;
+        let result = futures::executor::block_on(get_addons(&config));
+        assert!(matches!(result, Err(Error::MissingApiKey { .. })));
+    }
+}
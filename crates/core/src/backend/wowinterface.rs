@@ -1,29 +1,93 @@
-use isahc::prelude::*;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
 
-use crate::backend::{Addon, Flavor, Source, Version};
+use crate::backend::{
+    request::Client, Addon, Checksums, Fetched, Flavor, GameVersion, Source, Version,
+};
+use crate::config::{Config, WowIConfig};
 use crate::error::Error;
-use crate::utility::{null_to_default, u64_to_string};
+use crate::utility::{
+    epoch_millis_to_datetime, normalize_text, null_to_default, number_and_string_to_i32, Lenient,
+};
 
 impl From<Package> for Addon {
     fn from(package: Package) -> Self {
         let categories =
             category_name_for_category_id(package.category_id).map_or(vec![], |c| vec![c]);
-        let flavor = flavor_for_category_id(package.category_id);
-        let version = extract_version_for_flavor(flavor, package.game_versions);
+
+        let folders = package
+            .addons
+            .iter()
+            .map(|a| a.path.clone())
+            .collect::<Vec<String>>();
+
+        let checksums = Checksums {
+            md5: package
+                .checksum
+                .as_deref()
+                .filter(|c| !c.is_empty())
+                .map(str::to_lowercase),
+            ..Checksums::default()
+        };
+
+        // One version for each flavor found in the game versions.
+        let mut flavors: Vec<(Flavor, Vec<GameVersion>)> = vec![];
+        let game_versions = package
+            .game_versions
+            .iter()
+            .filter_map(|v| v.parse::<GameVersion>().ok());
+        for game_version in game_versions {
+            if let Some(flavor) = game_version.flavor() {
+                match flavors.iter_mut().find(|(f, _)| *f == flavor) {
+                    Some((_, game_versions)) => game_versions.push(game_version),
+                    None => flavors.push((flavor, vec![game_version])),
+                }
+            }
+        }
+        let mut versions = flavors
+            .into_iter()
+            .map(|(flavor, game_versions)| Version {
+                download_url: Some(download_url_for_id(package.id)),
+                folders: folders.clone(),
+                checksums: checksums.clone(),
+                ..Version::new(flavor, game_versions, package.last_update)
+            })
+            .collect::<Vec<Version>>();
+        // Without any game versions, the category tells the flavor.
+        if versions.is_empty() {
+            versions.push(Version {
+                download_url: Some(download_url_for_id(package.id)),
+                folders: folders.clone(),
+                checksums: checksums.clone(),
+                ..Version::new(
+                    flavor_for_category_id(package.category_id),
+                    vec![],
+                    package.last_update,
+                )
+            });
+        }
 
         Addon {
             id: package.id,
+            source_id: None,
             name: package.title,
             url: package.file_info_uri,
             number_of_downloads: package.downloads,
-            // Currently API does not send any description.
+            // The file list has no description, see `get_summaries`.
             summary: "".to_owned(),
-            versions: vec![Version {
-                flavor,
-                game_version: version,
-                date: package.last_update,
-            }],
+            authors: Some(package.author)
+                .filter(|a| !a.is_empty())
+                .into_iter()
+                .collect(),
+            version: package.version,
+            logo_url: None,
+            screenshot_urls: vec![],
+            versions,
             categories,
             source: Source::WowI,
         }
@@ -37,18 +101,60 @@ struct Package {
     #[serde(deserialize_with = "null_to_default::deserialize")]
     category_id: i32,
     version: Option<String>,
-    #[serde(deserialize_with = "u64_to_string::deserialize")]
-    last_update: String,
+    #[serde(deserialize_with = "epoch_millis_to_datetime::deserialize")]
+    last_update: Option<DateTime<Utc>>,
     title: String,
     author: String,
     file_info_uri: String,
     downloads: u64,
     #[serde(deserialize_with = "null_to_default::deserialize")]
     game_versions: Vec<String>,
+    #[serde(default, deserialize_with = "null_to_default::deserialize")]
+    addons: Vec<Folder>,
+    /// MD5 of the download.
+    #[serde(default)]
+    checksum: Option<String>,
+}
+
+/// An addon folder in the download.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Folder {
+    path: String,
+}
+
+/// Details of a file. Only the description is used, the rest is part of
+/// the file list.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Details {
+    #[serde(deserialize_with = "number_and_string_to_i32::deserialize")]
+    id: i32,
+    #[serde(deserialize_with = "null_to_default::deserialize")]
+    description: String,
+}
+
+fn endpoint_for_files(config: &WowIConfig) -> String {
+    format!("{}/filelist.json", config.api_url.trim_end_matches('/'))
 }
 
-fn base_endpoint<'a>() -> &'a str {
-    "https://api.mmoui.com/v4/game/WOW/filelist.json"
+fn endpoint_for_details(config: &WowIConfig, ids: &[i32]) -> String {
+    let ids = ids
+        .iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<String>>()
+        .join(",");
+    format!(
+        "{}/filedetails/{}.json",
+        config.api_url.trim_end_matches('/'),
+        ids
+    )
+}
+
+/// Returns the download link of the newest file for an addon id.
+fn download_url_for_id(id: i32) -> String {
+    format!(
+        "https://cdn.wowinterface.com/downloads/getfile.php?id={}",
+        id
+    )
 }
 
 /// Returns `Flavor` for a category id `i32`.
@@ -56,40 +162,10 @@ fn base_endpoint<'a>() -> &'a str {
 /// category with addons.
 fn flavor_for_category_id(id: i32) -> Flavor {
     match id {
-        160 => Flavor::ClassicEra,
-        161 => Flavor::ClassicTbc,
-        _ => Flavor::Retail,
-    }
-}
-
-/// Trys to guess `Flavor` from version `String`.
-///
-/// Eg. `"9.0.5" => Flavor::Retail`
-fn guess_flavor_from_version(version: &str) -> Option<Flavor> {
-    match version.chars().next() {
-        // 9.x.x, 8.x.x, 7.x.x
-        Some('9' | '8' | '7') => Some(Flavor::Retail),
-        // 1.x.x
-        Some('1') => Some(Flavor::ClassicEra),
-        // 2.x.x
-        Some('2') => Some(Flavor::ClassicTbc),
-        _ => None,
-    }
-}
-
-/// Extract version from `Vec<String>` for specific `Flavor`.
-///
-/// Versions looks like: `["2.5.1", "9.0.5", "1.13.7"]`.
-/// Eg. for `Flavor::Retail` we want to extract `9.0.5`.
-fn extract_version_for_flavor(flavor: Flavor, versions: Vec<String>) -> Option<String> {
-    for version in versions {
-        let flavor_guess = guess_flavor_from_version(&version);
-        if flavor_guess == Some(flavor) {
-            return Some(version);
-        }
+        160 => Flavor::CLASSIC_ERA,
+        161 => Flavor::CLASSIC_TBC,
+        _ => Flavor::RETAIL,
     }
-
-    None
 }
 
 /// Returns category name as `String` for given category id `i32`.
@@ -157,14 +233,118 @@ fn category_name_for_category_id(id: i32) -> Option<String> {
     }
 }
 
-pub async fn get_addons() -> Result<Vec<Addon>, Error> {
-    let mut response = isahc::get_async(base_endpoint()).await?;
-    let packages = response.json::<Vec<Package>>().await?;
-    let addons = packages
+/// Returns summaries by addon id from a previous catalog, for addons last
+/// updated at the same time as now.
+///
+/// A missing or unreadable catalog yields no summaries.
+fn cached_summaries(path: &Path, addons: &[Addon]) -> HashMap<i32, String> {
+    let previous = File::open(path).map_err(Error::from).and_then(|file| {
+        Ok(serde_json::from_reader::<_, Vec<Addon>>(BufReader::new(
+            file,
+        ))?)
+    });
+    let previous = match previous {
+        Ok(previous) => previous,
+        Err(error) => {
+            log::warn!(
+                "{}: could not read previous catalog {}: {}",
+                Source::WowI,
+                path.display(),
+                error
+            );
+            return HashMap::new();
+        }
+    };
+
+    // Catalogs keep whole seconds, so dates are compared at that precision.
+    let date = |addon: &Addon| {
+        addon
+            .versions
+            .first()
+            .and_then(|v| v.date)
+            .map(|d| d.timestamp())
+    };
+    let dates = addons
+        .iter()
+        .map(|addon| (addon.id, date(addon)))
+        .collect::<HashMap<i32, Option<i64>>>();
+    previous
+        .into_iter()
+        .filter(|addon| addon.source == Source::WowI && !addon.summary.is_empty())
+        .filter(|addon| dates.get(&addon.id) == Some(&date(addon)))
+        .map(|addon| (addon.id, addon.summary))
+        .collect()
+}
+
+/// Fetches descriptions for `ids` in batches and returns summaries by id.
+///
+/// A failed batch is logged and leaves its addons without a summary.
+async fn get_summaries(client: &Client, config: &WowIConfig, ids: &[i32]) -> HashMap<i32, String> {
+    let endpoints = ids
+        .chunks(config.details_batch_size.max(1))
+        .map(|ids| endpoint_for_details(config, ids))
+        .collect::<Vec<String>>();
+    let batches = stream::iter(endpoints)
+        .map(|endpoint| async move { client.get_json::<Lenient<Details>>(&endpoint).await })
+        .buffered(config.concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut summaries = HashMap::new();
+    for batch in batches {
+        match batch {
+            Ok(details) => {
+                details.log_skipped(Source::WowI);
+                summaries.extend(
+                    details
+                        .items
+                        .into_iter()
+                        .map(|d| (d.id, normalize_text(&d.description))),
+                );
+            }
+            Err(error) => log::warn!("{}: could not fetch descriptions: {}", Source::WowI, error),
+        }
+    }
+
+    summaries
+}
+
+pub async fn get_addons(config: &Config) -> Result<Fetched, Error> {
+    let wowi = &config.wowi;
+    let client = Client::new(Source::WowI, &wowi.request);
+    let packages = client
+        .get_json::<Lenient<Package>>(&endpoint_for_files(wowi))
+        .await?;
+    packages.log_skipped(Source::WowI);
+    let mut addons = packages
+        .items
         .into_iter()
         .map(Addon::from)
         .collect::<Vec<Addon>>();
-    Ok(addons)
+
+    // The file list has no descriptions, they are fetched per file unless
+    // the addon is unchanged since the previous catalog.
+    let mut summaries = wowi
+        .previous_catalog
+        .as_deref()
+        .map_or_else(HashMap::new, |path| cached_summaries(path, &addons));
+    let missing = addons
+        .iter()
+        .map(|addon| addon.id)
+        .filter(|id| !summaries.contains_key(id))
+        .collect::<Vec<i32>>();
+    summaries.extend(get_summaries(&client, wowi, &missing).await);
+    for addon in addons.iter_mut() {
+        if let Some(summary) = summaries.remove(&addon.id) {
+            addon.summary = summary;
+        }
+    }
+
+    Ok(Fetched {
+        skipped: packages.skipped.len(),
+        addons,
+        ..Fetched::default()
+    })
 }
 
 #[test]
@@ -191,4 +371,155 @@ fn test_null_fields() {
     for test in tests.iter() {
         serde_json::from_str::<Vec<Package>>(test).unwrap();
     }
+
+    // An empty author is not listed.
+    let package = serde_json::from_str::<Package>(
+        r#"{
+            "id": 38,
+            "categoryId": 17,
+            "version": null,
+            "lastUpdate": 1622059572000,
+            "title": "Foo",
+            "author": "",
+            "fileInfoUri": "",
+            "downloads": 0,
+            "gameVersions": []
+        }"#,
+    )
+    .unwrap();
+    assert!(Addon::from(package).authors.is_empty());
+}
+
+#[test]
+fn test_versions() {
+    let tests = [
+        (
+            r#"["2.5.1", "9.0.5", "1.13.7"]"#,
+            161,
+            vec![Flavor::RETAIL, Flavor::CLASSIC_ERA, Flavor::CLASSIC_TBC],
+        ),
+        (r#"["9.0.5", "9.0.2"]"#, 17, vec![Flavor::RETAIL]),
+        (r#"[]"#, 160, vec![Flavor::CLASSIC_ERA]),
+    ];
+
+    for (game_versions, category_id, flavors) in tests.iter() {
+        let package = serde_json::from_str::<Package>(&format!(
+            r#"{{
+                "id": 38,
+                "categoryId": {},
+                "version": "9.0.5.7",
+                "lastUpdate": 1622059572000,
+                "title": "Foo",
+                "author": "Bar",
+                "fileInfoUri": "",
+                "downloads": 593294,
+                "gameVersions": {}
+            }}"#,
+            category_id, game_versions
+        ))
+        .unwrap();
+
+        let mut addon = Addon::from(package);
+        addon.sort_versions();
+        let result = addon.versions.iter().map(|v| v.flavor).collect::<Vec<_>>();
+        assert_eq!(result, *flavors);
+    }
+
+    // Every game version of a flavor is kept, and the newest is primary.
+    let package = serde_json::from_str::<Package>(
+        r#"{
+            "id": 38,
+            "categoryId": 17,
+            "version": "9.0.5.7",
+            "lastUpdate": 1622059572000,
+            "title": "Foo",
+            "author": "Bar",
+            "fileInfoUri": "",
+            "downloads": 593294,
+            "gameVersions": ["9.0.2", "9.0.5", "9.0.2"],
+            "addons": [{ "path": "Foo" }, { "path": "Foo_Options" }]
+        }"#,
+    )
+    .unwrap();
+    let addon = Addon::from(package);
+    let version = &addon.versions[0];
+    assert_eq!(
+        version.game_versions,
+        vec![GameVersion::new(9, 0, 2), GameVersion::new(9, 0, 5)]
+    );
+    assert_eq!(version.game_version, Some(GameVersion::new(9, 0, 5)));
+    assert_eq!(version.folders, vec!["Foo", "Foo_Options"]);
+    assert_eq!(addon.authors, vec!["Bar"]);
+}
+
+#[test]
+fn test_get_addons() {
+    use crate::backend::stand_in_server;
+
+    fn package(id: i32, last_update: u64) -> String {
+        format!(
+            r#"{{
+                "id": {},
+                "categoryId": 17,
+                "version": "1.0",
+                "lastUpdate": {},
+                "title": "Foo",
+                "author": "Bar",
+                "fileInfoUri": "",
+                "downloads": 10,
+                "gameVersions": ["9.0.5"]
+            }}"#,
+            id, last_update
+        )
+    }
+
+    // Local stand-in for the WoWInterface API. Only addon `2` changed since
+    // the previous catalog, so only its details are requested. Addon `1` is
+    // updated at a fraction of a second, which the catalog does not keep.
+    let api_url = stand_in_server(|path| match path {
+        "/filelist.json" => Some(format!(
+            "[{}, {}]",
+            package(1, 1622059572123),
+            package(2, 1622059572000)
+        )),
+        "/filedetails/2.json" => {
+            Some(r#"[{ "id": "2", "description": "[b]Fresh[/b]\r\ndescription" }]"#.to_owned())
+        }
+        _ => None,
+    });
+
+    let previous = serde_json::json!([
+        {
+            "id": 1,
+            "name": "Foo",
+            "url": "",
+            "number_of_downloads": 10,
+            "summary": "Cached description",
+            "versions": [{ "flavor": "Retail", "date": "2021-05-26T20:06:12Z" }],
+            "categories": [],
+            "source": "WowI"
+        },
+        {
+            "id": 2,
+            "name": "Foo",
+            "url": "",
+            "number_of_downloads": 10,
+            "summary": "Outdated description",
+            "versions": [{ "flavor": "Retail", "date": "2021-05-01T00:00:00Z" }],
+            "categories": [],
+            "source": "WowI"
+        }
+    ]);
+    let path = std::env::temp_dir().join(format!("catalog-wowi-{}.json", std::process::id()));
+    std::fs::write(&path, previous.to_string()).unwrap();
+
+    let mut config = Config::default();
+    config.wowi.api_url = api_url;
+    config.wowi.previous_catalog = Some(path.clone());
+
+    let fetched = futures::executor::block_on(get_addons(&config)).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(fetched.addons.len(), 2);
+    assert_eq!(fetched.addons[0].summary, "Cached description");
+    assert_eq!(fetched.addons[1].summary, "Fresh description");
 }
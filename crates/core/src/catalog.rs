@@ -0,0 +1,166 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::backend::{Addon, Fetched, Source};
+use crate::error::Error;
+use crate::report::{Report, SourceReport, Status};
+use crate::utility::Lenient;
+
+/// Reads a previously written catalog.
+///
+/// Entries which no longer parse are skipped. Returns an empty catalog if the
+/// file is missing or cannot be parsed.
+pub fn load(path: &Path) -> Vec<Addon> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) => {
+            log::warn!(
+                "could not open previous catalog {}: {}",
+                path.display(),
+                error
+            );
+            return vec![];
+        }
+    };
+
+    match serde_json::from_reader::<_, Lenient<Addon>>(BufReader::new(file)) {
+        Ok(catalog) => {
+            for skipped in catalog.skipped.iter() {
+                log::warn!(
+                    "skipped entry {} of previous catalog {}: {}",
+                    skipped.id.as_deref().unwrap_or("without id"),
+                    path.display(),
+                    skipped.error
+                );
+            }
+            catalog.items
+        }
+        Err(error) => {
+            log::warn!(
+                "could not parse previous catalog {}: {}",
+                path.display(),
+                error
+            );
+            vec![]
+        }
+    }
+}
+
+/// Combines the result of each source into the addons of the catalog and a
+/// `Report`.
+///
+/// The addons of a failed source are carried over from `previous`, which is
+/// only called if a source failed.
+pub fn assemble<F>(
+    results: Vec<(Source, Result<Fetched, Error>)>,
+    previous: F,
+) -> (Vec<Addon>, Report)
+where
+    F: FnOnce() -> Vec<Addon>,
+{
+    let mut previous = Some(previous);
+    let mut loaded: Option<Vec<Addon>> = None;
+    let mut report = Report::default();
+    let mut addons: Vec<Addon> = vec![];
+    for (source, result) in results {
+        let (partial, skipped, partitions, status) = match result {
+            Ok(fetched) => (
+                fetched.addons,
+                fetched.skipped,
+                fetched.partitions,
+                Status::Fresh,
+            ),
+            Err(error) => {
+                let loaded = loaded.get_or_insert_with(|| previous.take().map_or(vec![], |f| f()));
+                let partial = loaded
+                    .iter()
+                    .filter(|addon| addon.source == source)
+                    .cloned()
+                    .collect::<Vec<Addon>>();
+                let status = Status::Stale {
+                    error: error.to_string(),
+                };
+                (partial, 0, vec![], status)
+            }
+        };
+
+        report.sources.push(SourceReport {
+            source,
+            addons: partial.len(),
+            skipped,
+            partitions,
+            status,
+        });
+        addons.extend(partial);
+    }
+
+    (addons, report)
+}
+
+#[test]
+fn test_load() {
+    let path = std::env::temp_dir().join(format!("catalog-load-{}.json", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"[
+            { "id": 1, "name": "Foo", "url": "", "number_of_downloads": 0, "summary": "", "versions": [], "categories": [], "source": "Tukui" },
+            { "id": 2, "name": "Bar", "url": "", "number_of_downloads": 0, "summary": "", "versions": [], "categories": [], "source": "Unknown" }
+        ]"#,
+    )
+    .unwrap();
+
+    // The entry which no longer parses is skipped, the rest is kept.
+    let addons = load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(addons.len(), 1);
+    assert_eq!(addons[0].id, 1);
+
+    assert!(load(&path).is_empty());
+}
+
+#[test]
+fn test_assemble() {
+    let addon = |id: i32, source: Source| Addon {
+        id,
+        source_id: None,
+        name: String::new(),
+        url: String::new(),
+        number_of_downloads: 0,
+        summary: String::new(),
+        authors: vec![],
+        version: None,
+        logo_url: None,
+        screenshot_urls: vec![],
+        versions: vec![],
+        categories: vec![],
+        source,
+    };
+    let fetched = Fetched {
+        addons: vec![addon(1, Source::Tukui)],
+        skipped: 2,
+        ..Fetched::default()
+    };
+
+    // Nothing failed, so the previous catalog is not read.
+    let (addons, report) = assemble(vec![(Source::Tukui, Ok(fetched.clone()))], || {
+        panic!("previous catalog was read")
+    });
+    assert_eq!(addons.len(), 1);
+    assert_eq!(report.sources[0].skipped, 2);
+    assert!(!report.is_degraded());
+
+    // A failed source is carried over from the previous catalog.
+    let failed = Err(Error::MissingApiKey {
+        backend: Source::Wago,
+    });
+    let (addons, report) = assemble(
+        vec![(Source::Tukui, Ok(fetched)), (Source::Wago, failed)],
+        || vec![addon(2, Source::Tukui), addon(3, Source::Wago)],
+    );
+    let ids = addons.iter().map(|a| a.id).collect::<Vec<i32>>();
+    assert_eq!(ids, vec![1, 3]);
+    assert!(report.is_degraded());
+    assert_eq!(report.stale_sources(), vec![Source::Wago]);
+    assert_eq!(report.sources[1].addons, 1);
+}
@@ -0,0 +1,237 @@
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use crate::backend::Source;
+use crate::error::Error;
+
+/// Configuration for a catalog run.
+///
+/// Every section is optional, missing values fall back to their defaults.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub curse: CurseConfig,
+    pub tukui: RequestConfig,
+    pub wowi: WowIConfig,
+    pub hub: HubConfig,
+    pub github: GitHubConfig,
+    pub wago: WagoConfig,
+    /// Path to a JSON table mapping major game versions to flavors. Replaces
+    /// the built-in table, eg. when an expansion launches.
+    pub game_versions: Option<PathBuf>,
+}
+
+impl Config {
+    /// Reads `Config` from a JSON file.
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let file = File::open(path)?;
+        let config = serde_json::from_reader(BufReader::new(file))?;
+        Ok(config)
+    }
+
+    /// Returns `RequestConfig` for `Source`.
+    pub fn request(&self, source: Source) -> &RequestConfig {
+        match source {
+            Source::Curse => &self.curse.request,
+            Source::Tukui => &self.tukui,
+            Source::WowI => &self.wowi.request,
+            Source::Hub => &self.hub.request,
+            Source::GitHub => &self.github.request,
+            Source::Wago => &self.wago.request,
+        }
+    }
+
+    /// Returns `true` if `Source` should be part of the catalog.
+    ///
+    /// Sources which need credentials are only enabled once configured.
+    pub fn is_enabled(&self, source: Source) -> bool {
+        match source {
+            Source::Wago => self.wago.api_key().is_some(),
+            _ => true,
+        }
+    }
+}
+
+/// Retry and rate limit settings for requests against a single `Source`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RequestConfig {
+    /// Number of retries after the first attempt.
+    pub retries: u32,
+    /// Delay in milliseconds before the first retry. Doubled for each retry.
+    pub backoff_ms: u64,
+    /// Upper bound in milliseconds for a single retry delay, including one
+    /// asked for by the server with `Retry-After`.
+    pub max_backoff_ms: u64,
+    /// Maximum number of requests per second. `None` means no limit.
+    pub requests_per_second: Option<f64>,
+    /// Time in milliseconds a single attempt may take before it is retried.
+    pub timeout_ms: u64,
+}
+
+impl Default for RequestConfig {
+    fn default() -> Self {
+        RequestConfig {
+            retries: 3,
+            backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            requests_per_second: None,
+            timeout_ms: 60_000,
+        }
+    }
+}
+
+/// Settings for the Curse backend.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CurseConfig {
+    #[serde(flatten)]
+    pub request: RequestConfig,
+    /// Base URL of the CurseForge Core API.
+    pub api_url: String,
+    /// Key used to authenticate. Falls back to the `CURSEFORGE_API_KEY`
+    /// environment variable.
+    pub api_key: Option<String>,
+    /// Base URL of a mirror serving the retired search API. If set, it is
+    /// used instead of the CurseForge Core API.
+    pub legacy_api_url: Option<String>,
+    /// Number of pages, and of categories, fetched at the same time.
+    pub concurrency: usize,
+}
+
+impl CurseConfig {
+    /// Returns the configured API key, if any.
+    pub fn api_key(&self) -> Option<String> {
+        self.api_key
+            .clone()
+            .or_else(|| std::env::var("CURSEFORGE_API_KEY").ok())
+            .filter(|key| !key.is_empty())
+    }
+}
+
+impl Default for CurseConfig {
+    fn default() -> Self {
+        CurseConfig {
+            request: RequestConfig::default(),
+            api_url: "https://api.curseforge.com".to_owned(),
+            api_key: None,
+            legacy_api_url: None,
+            concurrency: 4,
+        }
+    }
+}
+
+/// Settings for the WoWInterface backend.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WowIConfig {
+    #[serde(flatten)]
+    pub request: RequestConfig,
+    /// Base URL of the WoWInterface API.
+    pub api_url: String,
+    /// Catalog written by an earlier run. Descriptions of addons which did
+    /// not change since are taken from it instead of being fetched again.
+    pub previous_catalog: Option<PathBuf>,
+    /// Number of files whose descriptions are requested at once.
+    pub details_batch_size: usize,
+    /// Number of description requests made at the same time.
+    pub concurrency: usize,
+}
+
+impl Default for WowIConfig {
+    fn default() -> Self {
+        WowIConfig {
+            request: RequestConfig::default(),
+            api_url: "https://api.mmoui.com/v4/game/WOW".to_owned(),
+            previous_catalog: None,
+            details_batch_size: 100,
+            concurrency: 4,
+        }
+    }
+}
+
+/// Settings for the Hub backend.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct HubConfig {
+    #[serde(flatten)]
+    pub request: RequestConfig,
+    /// Base URL of the Hub API.
+    pub api_url: String,
+}
+
+impl Default for HubConfig {
+    fn default() -> Self {
+        HubConfig {
+            request: RequestConfig::default(),
+            api_url: "https://hub.wowup.io".to_owned(),
+        }
+    }
+}
+
+/// Settings for the GitHub releases backend.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct GitHubConfig {
+    #[serde(flatten)]
+    pub request: RequestConfig,
+    /// Base URL of the GitHub API.
+    pub api_url: String,
+    /// Repositories to include, as `owner/name`.
+    pub repositories: Vec<String>,
+    /// Repositories with this topic are included as well.
+    pub topic: Option<String>,
+    /// Token used to authenticate. Falls back to the `GITHUB_TOKEN`
+    /// environment variable.
+    pub token: Option<String>,
+}
+
+impl Default for GitHubConfig {
+    fn default() -> Self {
+        GitHubConfig {
+            request: RequestConfig::default(),
+            api_url: "https://api.github.com".to_owned(),
+            repositories: vec![],
+            topic: None,
+            token: None,
+        }
+    }
+}
+
+/// Settings for the Wago Addons backend.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WagoConfig {
+    #[serde(flatten)]
+    pub request: RequestConfig,
+    /// Base URL of the Wago Addons API.
+    pub api_url: String,
+    /// Key used to authenticate. Falls back to the `WAGO_API_KEY`
+    /// environment variable.
+    pub api_key: Option<String>,
+    /// Catalog written by an earlier run. Addons keep the id they have in it.
+    pub previous_catalog: Option<PathBuf>,
+}
+
+impl WagoConfig {
+    /// Returns the configured API key, if any.
+    pub fn api_key(&self) -> Option<String> {
+        self.api_key
+            .clone()
+            .or_else(|| std::env::var("WAGO_API_KEY").ok())
+            .filter(|key| !key.is_empty())
+    }
+}
+
+impl Default for WagoConfig {
+    fn default() -> Self {
+        WagoConfig {
+            request: RequestConfig::default(),
+            api_url: "https://addons.wago.io/api/external".to_owned(),
+            api_key: None,
+            previous_catalog: None,
+        }
+    }
+}
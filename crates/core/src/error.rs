@@ -1,13 +1,32 @@
+use crate::backend::Source;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
-    #[error(transparent)]
-    Isahc(#[from] isahc::Error),
-    #[error(transparent)]
-    Http(#[from] isahc::http::Error),
+    #[error("{backend}: request to {url} failed: {inner}")]
+    Request {
+        backend: Source,
+        url: String,
+        #[source]
+        inner: isahc::Error,
+    },
+    #[error("{backend}: {url} returned status {status}: {body_snippet}")]
+    HttpStatus {
+        backend: Source,
+        url: String,
+        status: u16,
+        body_snippet: String,
+    },
+    #[error("{backend}: could not decode response from {url}: {inner}")]
+    Decode {
+        backend: Source,
+        url: String,
+        #[source]
+        inner: serde_json::Error,
+    },
+    #[error("{backend}: no API key is configured")]
+    MissingApiKey { backend: Source },
     #[error(transparent)]
     SerdeJson(#[from] serde_json::Error),
     #[error(transparent)]
     Io(#[from] std::io::Error),
-    #[error("unknown error")]
-    Unknown,
 }
@@ -1,3 +1,6 @@
 pub mod backend;
+pub mod catalog;
+pub mod config;
 pub mod error;
+pub mod report;
 pub mod utility;
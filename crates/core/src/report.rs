@@ -0,0 +1,70 @@
+use crate::backend::{Partition, Source};
+
+/// Outcome of a catalog run, one entry per `Source`.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub sources: Vec<SourceReport>,
+}
+
+impl Report {
+    /// Returns the sources which could not be fetched and were carried over
+    /// from the previous catalog.
+    pub fn stale_sources(&self) -> Vec<Source> {
+        self.sources
+            .iter()
+            .filter(|s| matches!(s.status, Status::Stale { .. }))
+            .map(|s| s.source)
+            .collect()
+    }
+
+    /// Returns `true` if one or more sources are stale.
+    pub fn is_degraded(&self) -> bool {
+        !self.stale_sources().is_empty()
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for source in self.sources.iter() {
+            writeln!(f, "{}", source)?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of fetching a single `Source`.
+#[derive(Debug, Clone)]
+pub struct SourceReport {
+    pub source: Source,
+    /// Number of addons from this source in the written catalog.
+    pub addons: usize,
+    /// Number of records which could not be decoded and were skipped.
+    pub skipped: usize,
+    /// Records fetched by each part, if the source is crawled in parts.
+    pub partitions: Vec<Partition>,
+    pub status: Status,
+}
+
+impl std::fmt::Display for SourceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} addons", self.source, self.addons)?;
+        if self.skipped > 0 {
+            write!(f, ", {} skipped", self.skipped)?;
+        }
+        if let Status::Stale { error } = &self.status {
+            write!(f, " (stale, carried over: {})", error)?;
+        }
+        for partition in self.partitions.iter() {
+            write!(f, "\n  {}: {}", partition.name, partition.count)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Status {
+    /// Addons were fetched from the source.
+    Fresh,
+    /// Fetching failed, addons were carried over from the previous catalog.
+    Stale { error: String },
+}
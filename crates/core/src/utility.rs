@@ -1,3 +1,10 @@
+use regex::Regex;
+use serde::de::{DeserializeOwned, Deserializer};
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+use crate::backend::Source;
+
 /// Deserialize a null to default value.
 pub mod null_to_default {
     use serde::{self, Deserialize, Deserializer};
@@ -31,7 +38,7 @@ pub mod number_and_string_to_i32 {
     }
 }
 
-/// Deserialize a `Number` and `String` to `u64`.
+/// Deserialize a `Number` and `String` to `u64`. A null is deserialized to `0`.
 pub mod number_and_string_to_u64 {
     use serde::{self, de, Deserialize, Deserializer};
 
@@ -41,24 +48,331 @@ pub mod number_and_string_to_u64 {
             serde_json::Value::Number(num) => num
                 .as_u64()
                 .ok_or_else(|| de::Error::custom(format!("Invalid number: {}", num)))?,
+            serde_json::Value::Null => 0,
             _ => return Err(de::Error::custom("wrong type")),
         })
     }
 }
 
-/// Deserialize a `u64` value to `String`.
-pub mod u64_to_string {
-    use serde::{self, de, Deserialize, Deserializer};
+/// Parsers for the timestamp formats used by the sources. Every timestamp is
+/// converted to UTC.
+pub mod timestamp {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+    /// Parses an ISO-8601 timestamp with offset, eg. `2021-04-26T22:42:55.958Z`.
+    pub fn parse_iso8601(s: &str) -> Result<DateTime<Utc>, String> {
+        DateTime::parse_from_rfc3339(s)
+            .map(|date| date.with_timezone(&Utc))
+            .map_err(|error| format!("invalid timestamp {:?}: {}", s, error))
+    }
+
+    /// Parses milliseconds since the Unix epoch.
+    pub fn parse_epoch_millis(millis: i64) -> Result<DateTime<Utc>, String> {
+        DateTime::from_timestamp_millis(millis)
+            .ok_or_else(|| format!("invalid timestamp {}", millis))
+    }
+
+    /// Parses a date, eg. `2021-06-01`, or a date and time without offset,
+    /// eg. `2021-06-01 10:00:00`, which is taken to be UTC.
+    pub fn parse_date(s: &str) -> Result<DateTime<Utc>, String> {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+            .or_else(|_| {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default())
+            })
+            .map(|date| date.and_utc())
+            .map_err(|error| format!("invalid date {:?}: {}", s, error))
+    }
+
+    /// Parses any of the formats above. Epoch milliseconds are accepted as
+    /// a string of digits.
+    pub fn parse_any(s: &str) -> Result<DateTime<Utc>, String> {
+        if let Ok(millis) = s.parse::<i64>() {
+            return parse_epoch_millis(millis);
+        }
+        parse_iso8601(s).or_else(|_| parse_date(s))
+    }
+}
+
+/// Returns the parsed timestamp, or logs why it is invalid and returns `None`.
+///
+/// A record with an invalid timestamp is kept, only without a date.
+fn valid_or_warn<T>(result: Result<T, String>) -> Option<T> {
+    result
+        .map_err(|error| log::warn!("{}, ignoring it", error))
+        .ok()
+}
+
+/// Deserialize an ISO-8601 `String` to `Option<DateTime<Utc>>`. A null or an
+/// invalid timestamp is deserialized to `None`.
+pub mod iso8601_to_datetime {
+    use chrono::{DateTime, Utc};
+    use serde::{self, Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?
+            .and_then(|s| super::valid_or_warn(super::timestamp::parse_iso8601(&s))))
+    }
+}
+
+/// Deserialize epoch milliseconds as a `Number` to `Option<DateTime<Utc>>`. A
+/// null or an invalid timestamp is deserialized to `None`.
+pub mod epoch_millis_to_datetime {
+    use chrono::{DateTime, Utc};
+    use serde::{self, Deserialize, Deserializer};
 
-    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
         Ok(match serde_json::Value::deserialize(deserializer)? {
-            serde_json::Value::Number(num) => {
-                let num = num
-                    .as_u64()
-                    .ok_or_else(|| de::Error::custom(format!("invalid number: {}", num)))?;
-                num.to_string()
-            }
-            _ => return Err(de::Error::custom("wrong type")),
+            serde_json::Value::Null => None,
+            serde_json::Value::Number(num) => super::valid_or_warn(
+                num.as_i64()
+                    .ok_or_else(|| format!("invalid timestamp {}", num))
+                    .and_then(super::timestamp::parse_epoch_millis),
+            ),
+            value => super::valid_or_warn(Err(format!("invalid timestamp {}", value))),
         })
     }
 }
+
+/// Deserialize a date `String` to `Option<DateTime<Utc>>`. A null, an empty
+/// string or an invalid date is deserialized to `None`.
+pub mod date_to_datetime {
+    use chrono::{DateTime, Utc};
+    use serde::{self, Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?
+            .filter(|s| !s.is_empty())
+            .and_then(|s| super::valid_or_warn(super::timestamp::parse_date(&s))))
+    }
+}
+
+/// (De)serialize the timestamp of a catalog entry.
+///
+/// Timestamps are written as RFC 3339 in UTC with whole seconds, eg.
+/// `2021-06-01T10:00:00Z`. Any format written by older catalogs is read, an
+/// invalid timestamp is read as `None`.
+pub mod catalog_datetime {
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{self, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        date: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match date {
+            Some(date) => {
+                serializer.serialize_str(&date.to_rfc3339_opts(SecondsFormat::Secs, true))
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?
+            .filter(|s| !s.is_empty())
+            .and_then(|s| super::valid_or_warn(super::timestamp::parse_any(&s))))
+    }
+}
+
+/// Deserialize a game version to `Option<GameVersion>`. A null, or a value
+/// which is not a game version, is deserialized to `None`.
+pub mod lenient_game_version {
+    use serde::{self, Deserialize, Deserializer};
+
+    use crate::backend::GameVersion;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<GameVersion>, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        Ok(serde_json::from_value(value).ok())
+    }
+}
+
+/// A list which skips elements that fail to deserialize as `T`, instead of
+/// failing the whole list.
+#[derive(Debug, Clone)]
+pub struct Lenient<T> {
+    pub items: Vec<T>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// An element which was skipped by `Lenient`.
+#[derive(Debug, Clone)]
+pub struct Skipped {
+    /// The `id` of the element, if it had one.
+    pub id: Option<String>,
+    pub error: String,
+}
+
+impl<T> Default for Lenient<T> {
+    fn default() -> Self {
+        Lenient {
+            items: vec![],
+            skipped: vec![],
+        }
+    }
+}
+
+impl<T> Lenient<T> {
+    /// Logs every skipped element for `source`.
+    pub fn log_skipped(&self, source: Source) {
+        for skipped in self.skipped.iter() {
+            log::warn!(
+                "{}: skipped record {}: {}",
+                source,
+                skipped.id.as_deref().unwrap_or("without id"),
+                skipped.error
+            );
+        }
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Lenient<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<serde_json::Value>::deserialize(deserializer)?;
+        let mut items = Vec::with_capacity(values.len());
+        let mut skipped = vec![];
+        for value in values {
+            let id = value.get("id").map(|id| match id {
+                serde_json::Value::String(s) => s.clone(),
+                id => id.to_string(),
+            });
+            match serde_json::from_value::<T>(value) {
+                Ok(item) => items.push(item),
+                Err(error) => skipped.push(Skipped {
+                    id,
+                    error: error.to_string(),
+                }),
+            }
+        }
+
+        Ok(Lenient { items, skipped })
+    }
+}
+
+/// Returns plain text for a description written in HTML or BBCode.
+///
+/// Tags are removed, common entities decoded and whitespace collapsed.
+pub fn normalize_text(text: &str) -> String {
+    static TAGS: OnceLock<Regex> = OnceLock::new();
+    let tags = TAGS.get_or_init(|| {
+        Regex::new(concat!(
+            r"(?i)<[^>]*>|\[img\][^\[]*\[/img\]",
+            r"|\[/?(b|i|u|s|url|email|img|color|size|font|list|\*|quote|code|center|left|right|indent|highlight)(=[^\]]*)?\]",
+        ))
+        .unwrap()
+    });
+
+    let text = tags.replace_all(text, " ");
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Returns a stable, non-negative `i32` for a non-numeric identifier.
+///
+/// Uses 32-bit FNV-1a, so the result is the same across runs and platforms.
+pub fn stable_id(id: &str) -> i32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in id.bytes() {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+
+    (hash & 0x7fff_ffff) as i32
+}
+
+#[test]
+fn test_lenient() {
+    #[derive(Deserialize)]
+    struct Package {
+        #[allow(dead_code)]
+        id: i32,
+    }
+
+    let lenient: Lenient<Package> =
+        serde_json::from_str(r#"[{"id": 1}, {"id": "foo"}, {"id": 3}, 4]"#).unwrap();
+    assert_eq!(lenient.items.len(), 2);
+    assert_eq!(lenient.skipped.len(), 2);
+    assert_eq!(lenient.skipped[0].id.as_deref(), Some("foo"));
+    assert_eq!(lenient.skipped[1].id, None);
+}
+
+#[test]
+fn test_timestamp() {
+    use timestamp::{parse_any, parse_date, parse_epoch_millis, parse_iso8601};
+
+    let expected = parse_iso8601("2021-06-01T10:00:00Z").unwrap();
+    assert_eq!(parse_iso8601("2021-06-01T12:00:00+02:00"), Ok(expected));
+    assert_eq!(parse_epoch_millis(1_622_541_600_000), Ok(expected));
+    assert_eq!(parse_date("2021-06-01 10:00:00"), Ok(expected));
+    assert_eq!(
+        parse_date("2021-06-01"),
+        parse_iso8601("2021-06-01T00:00:00Z")
+    );
+    assert_eq!(parse_any("1622541600000"), Ok(expected));
+    assert_eq!(parse_any("2021-06-01 10:00:00"), Ok(expected));
+    assert!(parse_iso8601("2021-06-01").is_err());
+    assert!(parse_date("0000-00-00").is_err());
+}
+
+#[test]
+fn test_invalid_timestamp() {
+    use chrono::{DateTime, Utc};
+
+    #[derive(Deserialize)]
+    struct Package {
+        #[serde(deserialize_with = "iso8601_to_datetime::deserialize")]
+        date: Option<DateTime<Utc>>,
+        #[serde(deserialize_with = "epoch_millis_to_datetime::deserialize")]
+        millis: Option<DateTime<Utc>>,
+    }
+
+    // Records with an invalid timestamp are kept, without a date.
+    let lenient: Lenient<Package> = serde_json::from_str(
+        r#"[
+            { "date": "2021-06-01T10:00:00Z", "millis": 1622541600000 },
+            { "date": "yesterday", "millis": "soon" },
+            { "date": null, "millis": null }
+        ]"#,
+    )
+    .unwrap();
+    assert_eq!(lenient.items.len(), 3);
+    assert_eq!(lenient.items[0].date, lenient.items[0].millis);
+    assert!(lenient.items[0].date.is_some());
+    assert_eq!(lenient.items[1].date, None);
+    assert_eq!(lenient.items[1].millis, None);
+    assert_eq!(lenient.items[2].date, None);
+}
+
+#[test]
+fn test_normalize_text() {
+    let tests = [
+        ("<p>Foo</p>", "Foo"),
+        ("[b]Foo[/b] [url=https://example.com]bar[/url]", "Foo bar"),
+        ("[list][*]Foo\r\n[*]Bar[/list]", "Foo Bar"),
+        ("Foo &amp; Bar&nbsp;&lt;3", "Foo & Bar <3"),
+        ("[IMG]https://example.com/a.png[/IMG]Foo", "Foo"),
+        ("Keep [this] text", "Keep [this] text"),
+    ];
+
+    for (text, expected) in tests.iter() {
+        assert_eq!(normalize_text(text), *expected);
+    }
+}
@@ -1,40 +1,90 @@
 use core::{
-    backend::{Backend, Source::*},
+    backend::{game_version, Backend, Source, Source::*},
+    catalog,
+    config::Config,
     error::Error,
+    report::Report,
 };
-use futures::{executor::block_on, try_join};
+use futures::{executor::block_on, future::join_all};
 use std::fs::File;
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
 use structopt::StructOpt;
 
 const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
 
+/// Exit code used when the catalog was written, but one or more sources
+/// failed and were carried over from the previous catalog.
+const EXIT_STALE: i32 = 2;
+
+/// Catalog written before the current format. It is no longer updated, but
+/// is read as the previous catalog until one with the current name exists.
+const LEGACY_FILE_NAME: &str = "catalog-0.2.0.json";
+
 fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
     let future = handle_opts();
-    let _ = block_on(future);
+    match block_on(future) {
+        Ok(report) if report.is_degraded() => process::exit(EXIT_STALE),
+        Ok(_) => {}
+        Err(error) => {
+            eprintln!("error: {}", error);
+            process::exit(1);
+        }
+    }
 }
 
-async fn handle_opts() -> Result<(), Error> {
+async fn handle_opts() -> Result<Report, Error> {
     let opts = Opts::from_args();
     match opts.command {
         // Generate a JSON file with all backend sources combined.
-        Command::Catalog => {
-            let (tukui, wowi, curse, hub) = try_join!(
-                Tukui.get_addons(),
-                WowI.get_addons(),
-                Curse.get_addons(),
-                Hub.get_addons()
-            )?;
-            // Combine all addons.
-            let concatenated = [&tukui[..], &wowi[..], &curse[..], &hub[..]].concat();
+        Command::Catalog { config } => {
+            let mut config = match config {
+                Some(path) => Config::load(&path)?,
+                None => Config::default(),
+            };
+            if let Some(path) = &config.game_versions {
+                game_version::load_table(path)?;
+            }
+            let file_name = format!("catalog-{}.json", VERSION.expect("no version was found"));
+            let previous_catalog = if Path::new(&file_name).exists() {
+                PathBuf::from(&file_name)
+            } else {
+                PathBuf::from(LEGACY_FILE_NAME)
+            };
+            // Unchanged WoWInterface descriptions and Wago ids are reused
+            // from the last run.
+            for previous in [
+                &mut config.wowi.previous_catalog,
+                &mut config.wago.previous_catalog,
+            ] {
+                previous.get_or_insert_with(|| previous_catalog.clone());
+            }
+            let sources = [Tukui, WowI, Curse, Hub, GitHub, Wago]
+                .iter()
+                .copied()
+                .filter(|source| config.is_enabled(*source))
+                .collect::<Vec<Source>>();
+            // Each source is fetched independently, so one failing source
+            // does not abort the others.
+            let results = join_all(sources.iter().map(|source| source.get_addons(&config))).await;
+
+            // Failed sources are carried over from the previous catalog,
+            // which is only read if a source failed.
+            let results = sources.iter().copied().zip(results).collect();
+            let (addons, report) = catalog::assemble(results, || catalog::load(&previous_catalog));
+
             // Serialize.
-            let json = serde_json::to_string(&concatenated)?;
+            let json = serde_json::to_string(&addons)?;
             // Create catalog file.
-            let file_name = format!("catalog-{}.json", VERSION.expect("no version was found"));
             let mut file = File::create(file_name)?;
             // Write to file.
             file.write_all(json.as_bytes())?;
-            Ok(())
+
+            eprint!("{}", report);
+            Ok(report)
         }
     }
 }
@@ -48,5 +98,9 @@ struct Opts {
 
 #[derive(Debug, StructOpt)]
 enum Command {
-    Catalog,
+    Catalog {
+        /// Path to a JSON configuration file.
+        #[structopt(long, parse(from_os_str))]
+        config: Option<PathBuf>,
+    },
 }